@@ -0,0 +1,12 @@
+fn main() {
+    if_chain! {
+        if let Some(x) = std::env::args().next(); // the program name
+        if x.len() > 1
+        /* a block comment */;
+        if x.starts_with("a_very_long_prefix_that_forces_wrapping")
+            && x.ends_with("a_very_long_suffix");
+        then {
+            println!("{}", x);
+        }
+    }
+}
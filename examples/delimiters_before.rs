@@ -0,0 +1,11 @@
+if_chain!(
+    if let Some(x) = f();   if x > 0;
+    then { g(x) }
+);
+
+fn main() {
+    if_chain!(if let Some(x) = f();   if x > 0;
+        then { g(x) });
+    let y = if_chain![if a; then { 1 } else { 2 }];
+    if_chain!(if a; then { h() })
+}
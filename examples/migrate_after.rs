@@ -0,0 +1,29 @@
+fn main() {
+    // The first condition
+    if let Some(x) = f()
+        && (x > 0 || x < -10)
+        && let y = x * 2
+        && let Ok(z) = g(y)
+    {
+        let w = z + 1;
+        h(w);
+    } else {
+        println!("no");
+    }
+    let v = {
+        let a = f();
+        if let Some(b) = a
+            && let c = (b && d)
+            && c
+        {
+            1
+        } else {
+            2
+        }
+    };
+    if a {
+        if let Some(q) = r {
+            s(q)
+        }
+    }
+}
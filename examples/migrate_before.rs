@@ -0,0 +1,33 @@
+use if_chain::if_chain;
+
+fn main() {
+    if_chain! {
+        // The first condition
+        if let Some(x) = f();
+        if x > 0 || x < -10;
+        let y = x * 2;
+        if let Ok(z) = g(y);
+        let w = z + 1;
+        then {
+            h(w);
+        } else {
+            println!("no");
+        }
+    }
+    let v = if_chain! {
+        let a = f();
+        if let Some(b) = a;
+        let c = b && d;
+        if c;
+        then { 1 } else { 2 }
+    };
+    if_chain! {
+        if a;
+        then {
+            if_chain! {
+                if let Some(q) = r;
+                then { s(q) }
+            }
+        }
+    }
+}
@@ -0,0 +1,3 @@
+fn main() {
+    if_chain! { if let Some (x) = foo () ; let y = if_chain! { if x . is_ready () ; then { x . value () } else { 0 } } ; then { if_chain! { if y > 0 ; if let Ok (z) = bar (y) ; then { println! ("{}", z) ; } else { eprintln! ("not positive") ; } } } }
+}
@@ -0,0 +1,17 @@
+fn main() {
+    if_chain::if_chain! {
+        if let Some(x) = std::env::args().next();
+        if x.len() > 1;
+        then {
+            println!("{}", x);
+        }
+    }
+    let _ = ::if_chain::if_chain! {
+        if true;
+        then {
+            1
+        } else {
+            0
+        }
+    };
+}
@@ -0,0 +1,27 @@
+fn formatted() {
+    if_chain! { if a ; then { b() } }
+}
+
+#[rustfmt::skip]
+fn aligned() {
+    if_chain! {
+        if let Some(x)   = f();
+        if x > 0        ;
+        then { g(x) }
+    }
+}
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+fn also_aligned() {
+    if_chain! { if a ; then { b() } }
+}
+
+#[rustfmt::skip::macros(if_chain)]
+fn macros() {
+    if_chain! { if a ; then { b() } }
+}
+
+fn statement() {
+    #[rustfmt::skip]
+    if_chain! { if a ; then { b() } }
+}
@@ -0,0 +1,63 @@
+use if_chain::if_chain;
+use std::collections::HashMap;
+
+fn main() {
+    let map = HashMap::from([("a", Some(1))]);
+    if_chain! {
+        if let Some(value) = map.get("a");
+        // smoelius: A comment between the conditions is kept.
+        if let Some(x) = value;
+        if *x > 0;
+        then {
+            println!("{x}");
+        }
+    }
+
+    // smoelius: An `if` with an `else` arm is not part of a chain.
+    if let Some(value) = map.get("b") {
+        if value.is_some() {
+            println!("b");
+        }
+    } else {
+        println!("no b");
+    }
+
+    // smoelius: Neither are plain `if`s, which are better combined with `&&`.
+    if map.len() > 0 {
+        if map.contains_key("a") {
+            println!("a");
+        }
+    }
+
+    let n = if let Some(Some(x)) = map.get("a") {
+        if let Some(y) = x.checked_add(1) {
+            if let Some(z) = y.checked_mul(2) {
+                z
+            } else {
+                0
+            }
+        } else {
+            0
+        }
+    } else {
+        0
+    };
+    println!("{n}");
+
+    for key in map.keys() {
+        if key.len() == 1 {
+            if_chain! {
+                if let Some(c) = key.chars().next();
+                if c.is_alphabetic();
+                if let Some(d) = c.to_digit(36);
+                then {
+                    println!("{d}");
+                }
+            }
+        } else if let Some(value) = map.get(key) {
+            if let Some(x) = value {
+                println!("{x}");
+            }
+        }
+    }
+}
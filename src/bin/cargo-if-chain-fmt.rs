@@ -0,0 +1,240 @@
+use anyhow::{Result, anyhow, bail, ensure};
+use jobserver::Client;
+use rustfmt_if_chain::FailedTo;
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    env::{self, consts::EXE_SUFFIX},
+    path::{Path, PathBuf},
+    process::{Command, exit},
+};
+
+#[derive(Default)]
+struct Args {
+    all: bool,
+    check: bool,
+    manifest_path: Option<String>,
+    packages: Vec<String>,
+    rustfmt_if_chain_args: Vec<String>,
+}
+
+fn main() -> Result<()> {
+    // smoelius: `from_env` is unsafe because the file descriptors named in the environment could
+    // have been closed and reused. Calling it before any files are opened avoids that.
+    let client = unsafe { Client::from_env() };
+
+    let args = process_args()?;
+
+    let metadata = cargo_metadata(args.manifest_path.as_deref())?;
+
+    let packages = selected_packages(&args, &metadata)?;
+
+    // smoelius: `rustfmt` needs to be told each crate's edition. So, like `cargo fmt`, group the
+    // crate roots by edition and run `rustfmt_if_chain` once per group.
+    let mut roots_by_edition = BTreeMap::<&str, Vec<&str>>::new();
+    for package in packages {
+        let edition = package["edition"].as_str().unwrap_or("2015");
+        let roots = roots_by_edition.entry(edition).or_default();
+        for target in package["targets"].as_array().into_iter().flatten() {
+            let src_path = target["src_path"]
+                .as_str()
+                .ok_or_else(|| anyhow!("target has no `src_path`: {target}"))?;
+            if !roots.contains(&src_path) {
+                roots.push(src_path);
+            }
+        }
+    }
+
+    let mut success = true;
+
+    // smoelius: The groups are formatted one at a time, so at most `-j` files (see ARGS) are
+    // formatted at once. A jobserver passed to this process is passed on, so that
+    // `rustfmt_if_chain` also acquires tokens from it.
+    for (edition, roots) in roots_by_edition {
+        let mut command = Command::new(rustfmt_if_chain());
+        if let Some(client) = &client {
+            client.configure(&mut command);
+        }
+        if args.check {
+            command.arg("--check");
+        }
+        if !args
+            .rustfmt_if_chain_args
+            .iter()
+            .any(|arg| arg == "--edition" || arg.starts_with("--edition="))
+        {
+            command.args(["--edition", edition]);
+        }
+        command.args(&args.rustfmt_if_chain_args);
+        command.args(roots);
+        let status = command
+            .status()
+            .failed_to(|| format!("get status of {command:?}"))?;
+        success &= status.success();
+    }
+
+    if !success {
+        exit(1);
+    }
+
+    Ok(())
+}
+
+fn process_args() -> Result<Args> {
+    let mut args = Args::default();
+    let mut iter = env::args().skip(1).peekable();
+    // smoelius: When run as `cargo if-chain-fmt`, Cargo passes the subcommand name as the first
+    // argument.
+    iter.next_if(|arg| arg == "if-chain-fmt");
+    while let Some(arg) = iter.next() {
+        if arg == "--help" || arg == "-h" {
+            usage();
+        } else if arg == "--all" {
+            args.all = true;
+        } else if arg == "--check" {
+            args.check = true;
+        } else if arg == "--manifest-path" {
+            args.manifest_path = Some(value(&arg, iter.next())?);
+        } else if let Some(value) = arg.strip_prefix("--manifest-path=") {
+            args.manifest_path = Some(value.to_owned());
+        } else if arg == "-p" || arg == "--package" {
+            args.packages.push(value(&arg, iter.next())?);
+        } else if let Some(value) = arg.strip_prefix("--package=") {
+            args.packages.push(value.to_owned());
+        } else if arg == "--" {
+            args.rustfmt_if_chain_args.extend(iter.by_ref());
+        } else {
+            bail!("unexpected argument `{arg}`; see `cargo if-chain-fmt --help`");
+        }
+    }
+    Ok(args)
+}
+
+fn value(arg: &str, value: Option<String>) -> Result<String> {
+    value.ok_or_else(|| anyhow!("`{arg}` requires a value"))
+}
+
+const USAGE: &str = "\
+Usage: cargo if-chain-fmt [OPTIONS] [-- ARGS]
+
+`--all`: Format all packages in the workspace.
+
+`--check`: Do not modify any source files. Instead, print a diff
+for each one that would change, and exit with 1 if any would.
+
+`--manifest-path PATH`: Use the workspace containing the
+`Cargo.toml` at PATH.
+
+`-p PACKAGE`, `--package PACKAGE`: Format only PACKAGE. May be
+given more than once.
+
+Without `--all` or `--package`, the package in the current
+directory is formatted, or every workspace member if the current
+directory is a virtual workspace's root. The crate roots of the
+selected packages' targets are passed to `rustfmt_if_chain`,
+along with ARGS.\
+";
+
+fn usage() -> ! {
+    println!("{USAGE}");
+    exit(0);
+}
+
+fn cargo_metadata(manifest_path: Option<&str>) -> Result<Value> {
+    let mut command = cargo();
+    command.args(["metadata", "--format-version=1", "--no-deps"]);
+    if let Some(manifest_path) = manifest_path {
+        command.args(["--manifest-path", manifest_path]);
+    }
+    let output = command
+        .output()
+        .failed_to(|| format!("get output of {command:?}"))?;
+    ensure!(
+        output.status.success(),
+        "{command:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).failed_to(|| format!("parse output of {command:?}"))
+}
+
+fn selected_packages<'a>(args: &Args, metadata: &'a Value) -> Result<Vec<&'a Value>> {
+    let workspace_members = metadata["workspace_members"]
+        .as_array()
+        .ok_or_else(|| anyhow!("`cargo metadata` output has no `workspace_members`"))?;
+    let members = metadata["packages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|package| workspace_members.contains(&package["id"]))
+        .collect::<Vec<_>>();
+
+    if args.all {
+        return Ok(members);
+    }
+
+    if !args.packages.is_empty() {
+        return args
+            .packages
+            .iter()
+            .map(|name| {
+                members
+                    .iter()
+                    .copied()
+                    .find(|package| package["name"] == name.as_str())
+                    .ok_or_else(|| anyhow!("package `{name}` is not a member of the workspace"))
+            })
+            .collect();
+    }
+
+    let manifest_path = locate_project(args.manifest_path.as_deref())?;
+
+    let current = members.iter().copied().find(|package| {
+        package["manifest_path"]
+            .as_str()
+            .is_some_and(|path| Path::new(path) == manifest_path)
+    });
+
+    // smoelius: If the current manifest does not belong to a package, it is a virtual manifest.
+    Ok(current.map_or(members, |package| vec![package]))
+}
+
+fn locate_project(manifest_path: Option<&str>) -> Result<PathBuf> {
+    let mut command = cargo();
+    command.args(["locate-project", "--message-format=plain"]);
+    if let Some(manifest_path) = manifest_path {
+        command.args(["--manifest-path", manifest_path]);
+    }
+    let output = command
+        .output()
+        .failed_to(|| format!("get output of {command:?}"))?;
+    ensure!(
+        output.status.success(),
+        "{command:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout =
+        String::from_utf8(output.stdout).failed_to(|| format!("decode output of {command:?}"))?;
+    Ok(PathBuf::from(stdout.trim_end()))
+}
+
+fn cargo() -> Command {
+    Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+}
+
+/// Returns the path of the `rustfmt_if_chain` binary installed alongside this one, or just
+/// `rustfmt_if_chain` if there is none.
+fn rustfmt_if_chain() -> PathBuf {
+    let file_name = format!("rustfmt_if_chain{EXE_SUFFIX}");
+    env::current_exe()
+        .ok()
+        .map(|current_exe| current_exe.with_file_name(&file_name))
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from(file_name))
+}
+
+#[test]
+fn readme_contains_usage() {
+    let readme =
+        std::fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("README.md")).unwrap();
+    assert!(readme.contains(USAGE));
+}
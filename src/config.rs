@@ -0,0 +1,169 @@
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    str::Chars,
+};
+
+/// Returns the `rustfmt` configuration file that applies to `path`, i.e., the file named by
+/// `--config-path` in `rustfmt_args`, if any, or else the nearest `rustfmt.toml` or
+/// `.rustfmt.toml` in `path`'s directory or its ancestors
+pub fn config_file(path: &Path, rustfmt_args: &[String]) -> Option<PathBuf> {
+    let mut iter = rustfmt_args.iter();
+    while let Some(arg) = iter.next() {
+        let config_path = if arg == "--config-path" {
+            iter.next().map(PathBuf::from)
+        } else {
+            arg.strip_prefix("--config-path=").map(PathBuf::from)
+        };
+        if let Some(config_path) = config_path {
+            return Some(if config_path.is_dir() {
+                find_config_in(&config_path)?
+            } else {
+                config_path
+            });
+        }
+    }
+
+    let dir = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .canonicalize()
+        .ok()?;
+
+    dir.ancestors().find_map(find_config_in)
+}
+
+fn find_config_in(dir: &Path) -> Option<PathBuf> {
+    ["rustfmt.toml", ".rustfmt.toml"]
+        .into_iter()
+        .map(|name| dir.join(name))
+        .find(|config| config.is_file())
+}
+
+/// Extracts the strings of the `key` array from `contents`, a TOML file. Only what `rustfmt.toml`
+/// files need is supported, i.e., a top-level key whose value is an array of strings, possibly
+/// spanning several lines.
+pub fn string_array(contents: &str, key: &str) -> Vec<String> {
+    let Some(offset) = value_offset(contents, key) else {
+        return Vec::new();
+    };
+
+    let mut chars = contents[offset..].chars();
+
+    if chars.next() != Some('[') {
+        return Vec::new();
+    }
+
+    let mut strings = Vec::new();
+    while let Some(c) = chars.next() {
+        match c {
+            ']' => break,
+            '#' => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '"' | '\'' => strings.push(string(&mut chars, c)),
+            _ => {}
+        }
+    }
+    strings
+}
+
+/// Extracts the string value of `key` from `contents`, a TOML file, e.g., `2024` from
+/// `edition = "2024"`. See [`string_array`].
+pub fn string_value(contents: &str, key: &str) -> Option<String> {
+    let offset = value_offset(contents, key)?;
+    let mut chars = contents[offset..].chars();
+    let quote = chars.next().filter(|&c| c == '"' || c == '\'')?;
+    Some(string(&mut chars, quote))
+}
+
+/// Returns the edition with which `rustfmt` parses `path`, i.e., the value of `--edition` (or of
+/// `edition` within `--config`) in `rustfmt_args`, if any, or else the `edition` setting of the
+/// configuration that applies to `path`, if any, or else `rustfmt`'s default, 2015
+pub fn edition(path: &Path, rustfmt_args: &[String]) -> String {
+    let mut iter = rustfmt_args.iter();
+    while let Some(arg) = iter.next() {
+        let value = match arg.as_str() {
+            "--edition" => iter.next().cloned(),
+            "--config" => iter.next().and_then(|config| config_edition(config)),
+            _ => arg
+                .strip_prefix("--edition=")
+                .map(str::to_owned)
+                .or_else(|| arg.strip_prefix("--config=").and_then(config_edition)),
+        };
+        if let Some(value) = value {
+            return value;
+        }
+    }
+
+    config_file(path, rustfmt_args)
+        .and_then(|config| read_to_string(config).ok())
+        .and_then(|contents| string_value(&contents, "edition"))
+        .unwrap_or_else(|| String::from("2015"))
+}
+
+/// Returns the edition in `config`, the value of a `--config` option, e.g., `edition=2024,...`
+fn config_edition(config: &str) -> Option<String> {
+    config
+        .split(',')
+        .find_map(|setting| setting.strip_prefix("edition="))
+        .map(str::to_owned)
+}
+
+/// Returns the offset within `contents` of the value of the top-level key `key`
+fn value_offset(contents: &str, key: &str) -> Option<usize> {
+    // smoelius: The value may span several lines, so the offset of the value within `contents` is
+    // found, rather than just the value's first line.
+    let mut line_offset = 0;
+    contents.split_inclusive('\n').find_map(|line| {
+        let offset = line_offset;
+        line_offset += line.len();
+        let rest = line.trim_start().strip_prefix(key)?;
+        let value = rest.trim_start().strip_prefix('=')?.trim_start();
+        Some(offset + line.len() - value.len())
+    })
+}
+
+/// Reads a string whose opening `quote` has been consumed from `chars`
+fn string(chars: &mut Chars, quote: char) -> String {
+    let mut string = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            _ if c == quote => break,
+            '\\' if quote == '"' => string.extend(chars.next()),
+            _ => string.push(c),
+        }
+    }
+    string
+}
+
+#[test]
+fn string_array_handles_arrays() {
+    assert_eq!(
+        vec!["if_chain", "my_crate::chain", "*"],
+        string_array(
+            "edition = \"2021\"
+skip_macro_invocations = [
+    \"if_chain\", # comment
+    'my_crate::chain',
+    \"*\",
+]
+max_width = 80
+",
+            "skip_macro_invocations"
+        )
+    );
+    assert!(string_array("max_width = 80\n", "skip_macro_invocations").is_empty());
+}
+
+#[test]
+fn string_value_handles_quotes() {
+    let contents = "max_width = 80\nedition = \"2024\"\nstyle_edition = '2021'\n";
+    assert_eq!(Some("2024"), string_value(contents, "edition").as_deref());
+    assert_eq!(
+        Some("2021"),
+        string_value(contents, "style_edition").as_deref()
+    );
+    assert_eq!(None, string_value(contents, "max_width"));
+}
@@ -1,7 +1,13 @@
 use anyhow::{Context, Result};
 use std::fmt::Display;
 
+/// Adds context of the form "failed to ..." to errors
 pub trait FailedTo<T, E> {
+    /// Wraps the error, if any, in context "failed to ...", where "..." is what `f` returns
+    ///
+    /// # Errors
+    ///
+    /// If `self` is an error.
     fn failed_to<F, D>(self, f: F) -> Result<T>
     where
         F: FnOnce() -> D,
@@ -0,0 +1,167 @@
+use anyhow::{Result, anyhow, ensure};
+use rustfmt_if_chain::FailedTo;
+use std::{
+    ffi::OsStr,
+    fs::{OpenOptions, create_dir_all, write},
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use tempfile::NamedTempFile;
+
+/// The pre-commit hook written by `install-hook`
+const PRE_COMMIT: &str = "\
+#!/bin/sh
+# Installed by `rustfmt_if_chain install-hook`
+exec rustfmt_if_chain --check --index
+";
+
+/// Returns the `.rs` files within the current directory whose contents in the working tree, or with
+/// `staged`, in the index, differ from those in `since`. `since` defaults to `HEAD` with `staged`,
+/// and to the index otherwise. Deleted files are omitted.
+pub fn changed_files(since: Option<&str>, staged: bool) -> Result<Vec<PathBuf>> {
+    let mut args = vec!["diff", "--name-only", "--relative", "--diff-filter=d", "-z"];
+    if staged {
+        args.push("--cached");
+    }
+    args.extend(since);
+    args.push("--");
+
+    let stdout = git(args)?;
+
+    Ok(stdout
+        .split('\0')
+        .filter(|path| {
+            Path::new(path)
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("rs"))
+        })
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Writes a pre-commit hook that runs `rustfmt_if_chain --check --index` to the current
+/// repository. An existing hook is not overwritten. Returns the hook's path.
+pub fn install_hook() -> Result<PathBuf> {
+    // smoelius: `--git-path` respects `core.hooksPath` and works within linked worktrees.
+    let path = PathBuf::from(git(["rev-parse", "--git-path", "hooks/pre-commit"])?.trim_end());
+
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).failed_to(|| format!("create `{}`", parent.display()))?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o755);
+    let mut file = options
+        .open(&path)
+        .failed_to(|| format!("create `{}`", path.display()))?;
+    file.write_all(PRE_COMMIT.as_bytes())
+        .failed_to(|| format!("write to `{}`", path.display()))?;
+
+    Ok(path)
+}
+
+/// Returns the contents of `path`'s staged blob
+pub fn staged_contents(path: &Path) -> Result<String> {
+    // smoelius: `:./` makes the path relative to the current directory rather than the repository's
+    // root, as are the paths `changed_files` returns.
+    git([
+        OsStr::new("cat-file"),
+        OsStr::new("blob"),
+        format!(":./{}", path.display()).as_ref(),
+    ])
+}
+
+/// Replaces `path`'s staged blob with one holding `contents`, keeping the blob's mode
+pub fn update_index(path: &Path, contents: &str) -> Result<()> {
+    // smoelius: `--cacheinfo` takes a path relative to the repository's root, not to the current
+    // directory, so the path is obtained with `--full-name`. Each entry has the form
+    // `<mode> <object> <stage>\t<path>`.
+    let stage = git([
+        OsStr::new("ls-files"),
+        "--stage".as_ref(),
+        "--full-name".as_ref(),
+        "-z".as_ref(),
+        "--".as_ref(),
+        path.as_ref(),
+    ])?;
+    let (mode, full_name) = stage
+        .split_once('\t')
+        .and_then(|(info, rest)| {
+            let mode = info.split_whitespace().next()?;
+            let full_name = rest.split('\0').next()?;
+            Some((mode, full_name))
+        })
+        .ok_or_else(|| anyhow!("`{}` is not staged", path.display()))?;
+
+    let blob = tempfile_with(contents)?;
+    let object = git([
+        OsStr::new("hash-object"),
+        "-w".as_ref(),
+        "--no-filters".as_ref(),
+        blob.path().as_ref(),
+    ])?;
+
+    git([
+        OsStr::new("update-index"),
+        "--cacheinfo".as_ref(),
+        format!("{mode},{},{full_name}", object.trim_end()).as_ref(),
+    ])?;
+
+    Ok(())
+}
+
+/// Applies the change from `original` to `formatted` to the contents of `path` in the working tree
+/// with `git merge-file`. Returns false if the change does not apply cleanly, in which case `path`
+/// is not modified.
+pub fn apply_to_working_tree(path: &Path, original: &str, formatted: &str) -> Result<bool> {
+    let base = tempfile_with(original)?;
+    let other = tempfile_with(formatted)?;
+
+    let mut command = Command::new("git");
+    command
+        .args(["merge-file", "--stdout"])
+        .args([path, base.path(), other.path()]);
+    let output = command
+        .output()
+        .failed_to(|| format!("get output of {command:?}"))?;
+
+    // smoelius: `git merge-file` exits with the number of conflicts, or with a negative status if
+    // it cannot merge at all, e.g., because `path` was deleted.
+    if !output.status.success() {
+        return Ok(false);
+    }
+
+    write(path, output.stdout).failed_to(|| format!("write to `{}`", path.display()))?;
+
+    Ok(true)
+}
+
+fn tempfile_with(contents: &str) -> Result<NamedTempFile> {
+    let mut tempfile = NamedTempFile::new().failed_to(|| "create temporary file")?;
+    tempfile
+        .write_all(contents.as_bytes())
+        .failed_to(|| format!("write to `{}`", tempfile.path().display()))?;
+    Ok(tempfile)
+}
+
+/// Runs `git` with `args` and returns its stdout
+fn git<I, S>(args: I) -> Result<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut command = Command::new("git");
+    command.args(args);
+    let output = command
+        .output()
+        .failed_to(|| format!("get output of {command:?}"))?;
+    ensure!(
+        output.status.success(),
+        "{command:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).failed_to(|| format!("decode output of {command:?}"))
+}
@@ -0,0 +1,58 @@
+use syn::{
+    Block, Expr, Local, Stmt, Token,
+    parse::{Parse, ParseStream},
+};
+
+syn::custom_keyword!(then);
+
+/// The parsed body of an `if_chain!` invocation
+pub struct IfChainBody {
+    pub clauses: Vec<Clause>,
+    pub then_token: then,
+    pub then_branch: Block,
+    pub else_branch: Option<(Token![else], Block)>,
+}
+
+pub enum Clause {
+    /// `if <expr>;` or `if let <pat> = <expr>;`
+    If(Token![if], Expr, Token![;]),
+    /// `let <pat> = <expr>;`
+    Let(Local),
+}
+
+impl Parse for IfChainBody {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut clauses = Vec::new();
+        while !input.peek(then) {
+            clauses.push(input.parse()?);
+        }
+        let then_token = input.parse()?;
+        let then_branch = input.parse()?;
+        let else_branch = if input.peek(Token![else]) {
+            Some((input.parse()?, input.parse()?))
+        } else {
+            None
+        };
+        Ok(Self {
+            clauses,
+            then_token,
+            then_branch,
+            else_branch,
+        })
+    }
+}
+
+impl Parse for Clause {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(Token![if]) {
+            Ok(Self::If(input.parse()?, input.parse()?, input.parse()?))
+        } else if input.peek(Token![let]) {
+            match input.parse()? {
+                Stmt::Local(local) => Ok(Self::Let(local)),
+                _ => Err(input.error("expected `let` statement")),
+            }
+        } else {
+            Err(input.error("expected `if`, `let`, or `then`"))
+        }
+    }
+}
@@ -0,0 +1,955 @@
+//! An `if_chain`-aware `rustfmt` (kind of)
+//!
+//! [`format_str`] formats source held in memory. [`format_file`] formats a file and the files of
+//! the modules it declares in place. [`format_files`] formats many files in place at once, and
+//! [`format_sources`] formats many sources in memory at once.
+
+use anyhow::{Error, Result, anyhow, ensure};
+use proc_macro2::Ident;
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Formatter},
+    fs::{OpenOptions, copy, metadata, read_to_string, set_permissions},
+    io::Write,
+    mem::take,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tempfile::{Builder, NamedTempFile};
+
+mod config;
+use config::edition;
+
+mod failed_to;
+#[doc(hidden)]
+pub use failed_to::FailedTo;
+
+mod if_chain_body;
+
+mod migrate;
+use migrate::migrate_to_let_chains;
+
+mod modules;
+#[doc(hidden)]
+pub use modules::module_files;
+
+mod names;
+use names::MacroNames;
+
+mod restore;
+use restore::restore_if_chain;
+
+mod rewrite;
+use rewrite::{Invocation, Rewritten, rewrite_if_chain, unused_ident};
+
+mod rustfmt;
+use rustfmt::{rustfmt, rustfmt_batch, rustfmt_stdin};
+
+mod skip;
+use skip::skip_macro_invocations;
+
+mod suggest;
+use suggest::suggest_if_chain;
+
+mod verify;
+use verify::verify;
+
+mod walk;
+#[doc(hidden)]
+pub use walk::{Exclusions, rust_files};
+
+/// Options for [`format_str`], [`format_file`], [`format_files`], and [`format_sources`]
+///
+/// New options may be added in minor releases, so `Options` can only be created with
+/// [`Options::default`] and then modified.
+#[derive(Clone, Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
+#[non_exhaustive]
+pub struct Options {
+    /// Arguments passed to `rustfmt`
+    pub rustfmt_args: Vec<String>,
+    /// The path [`format_str`]'s source is treated as having been read from. It is used to find
+    /// `rustfmt`'s configuration and in messages.
+    pub path: Option<PathBuf>,
+    /// If `rustfmt` fails on the unmodified source, report a warning and leave the source unchanged
+    /// instead of failing.
+    pub preformat_failure_is_warning: bool,
+    /// Do not check that rewriting the source's `if_chain!` invocations for `rustfmt`, and
+    /// restoring them afterward, changed nothing else. Without this option, a result that fails
+    /// the check is returned as an error.
+    pub no_verify: bool,
+    /// Paths that refer to the `if_chain` crate's macro regardless of the `use` items in scope,
+    /// e.g., `chain` or `my_crate::chain`
+    pub macro_names: Vec<String>,
+    /// The crate root of [`format_str`]'s source. Its `extern crate` items and `macro_rules!`
+    /// definitions are taken into account when deciding which macros are `if_chain!`.
+    pub crate_root: Option<PathBuf>,
+    /// Convert `if_chain!( ... )` and `if_chain![ ... ]` to `if_chain! { ... }`. Without this
+    /// option, each invocation keeps its delimiters.
+    pub convert_to_braces: bool,
+    /// Rewrite `if_chain!` invocations into `if` expressions that use let chains. Invocations that
+    /// cannot be rewritten are reported and formatted as usual.
+    pub migrate_to_let_chains: bool,
+    /// Rewrite nested `if` expressions that could be a single `if_chain!` invocation into one,
+    /// e.g., `if let Some(x) = f() { if x > 0 { ... } }` into
+    /// `if_chain! { if let Some(x) = f(); if x > 0; then { ... } }`.
+    pub suggest_if_chain: bool,
+    /// Before [`format_file`] or [`format_files`] replaces a file, copy the original to a file with
+    /// the same name and the extension `.bk`, as `rustfmt --backup` does
+    pub backup: bool,
+    /// Receives the warnings produced while formatting, e.g., about malformed invocations
+    pub on_warning: WarningHandler,
+}
+
+/// A function that receives warnings (see [`Options::on_warning`]). The default handler discards
+/// them.
+#[derive(Clone, Default)]
+pub struct WarningHandler(Option<Arc<WarningFn>>);
+
+type WarningFn = dyn Fn(&str) + Send + Sync;
+
+impl WarningHandler {
+    /// Returns a handler that calls `f` with each warning. `f` may be called from several threads
+    /// by [`format_files`].
+    pub fn new(f: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        Self(Some(Arc::new(f)))
+    }
+
+    fn warn(&self, warning: &str) {
+        if let Some(f) = &self.0 {
+            f(warning);
+        }
+    }
+}
+
+impl Debug for WarningHandler {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("WarningHandler").finish_non_exhaustive()
+    }
+}
+
+/// A file to be formatted by [`format_files`]
+#[derive(Clone, Debug)]
+pub struct SourceFile {
+    /// The file's path
+    pub path: PathBuf,
+    /// The root of the crate to which the file belongs, if known. See [`Options::crate_root`].
+    pub crate_root: Option<PathBuf>,
+}
+
+/// Formats `source`, including the bodies of its `if_chain!` invocations, and returns the result
+///
+/// # Errors
+///
+/// If `source` cannot be parsed, if `rustfmt` fails, or if verification fails (see
+/// [`Options::no_verify`]).
+pub fn format_str(source: &str, options: &Options) -> Result<String> {
+    let path = options.path.as_deref().unwrap_or(Path::new("<stdin>"));
+
+    if let Err(error) = rustfmt_stdin(&options.rustfmt_args, path, source) {
+        if options.preformat_failure_is_warning {
+            options.on_warning.warn(&error.to_string());
+            return Ok(source.to_owned());
+        }
+        return Err(error);
+    }
+
+    let names = crate_macro_names(options.crate_root.as_deref(), options);
+
+    let transformed = transform(path, source, &names, options)?;
+    let source = transformed.as_deref().unwrap_or(source);
+
+    let Pass {
+        contents, warnings, ..
+    } = format_pass(path, source, &names, options)?;
+
+    warn(&warnings, options);
+
+    let progress = Progress {
+        input: source.to_owned(),
+        contents,
+    };
+
+    let mut progress = reach_fixpoints(&[path], vec![Ok(progress)], options, |_, sources| {
+        sources
+            .iter()
+            .map(|source| format_pass(path, source, &names, options))
+            .collect()
+    });
+
+    progress.remove(0).map(|progress| progress.contents)
+}
+
+/// Formats the file at `path` in place, including the bodies of its `if_chain!` invocations
+///
+/// Like `rustfmt`, the files of the out-of-line modules reachable from `path` are formatted too.
+/// See [`module_files`].
+///
+/// No file is modified until all of them have been formatted. Each file is then replaced
+/// atomically, and only if its contents changed.
+///
+/// # Errors
+///
+/// If any of the files cannot be read, parsed, or written, if `rustfmt` fails, or if verification
+/// fails (see [`Options::no_verify`]). In each case, no file is modified.
+pub fn format_file(path: &Path, options: &Options) -> Result<()> {
+    let files = module_files(path)?
+        .into_iter()
+        .map(|module_path| SourceFile {
+            path: module_path,
+            crate_root: Some(path.to_owned()),
+        })
+        .collect::<Vec<_>>();
+
+    let tempfiles = write_copies(format_batch(&files, read_files(&files), options))
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+
+    for (file, tempfile) in files.iter().zip(tempfiles) {
+        if let Some(tempfile) = tempfile {
+            replace(&file.path, tempfile, options)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Formats `files` in place, running `rustfmt` once for all of them
+///
+/// Unlike [`format_file`], out-of-line modules are not followed. Returns one result per file,
+/// which is `true` if the file was modified. Each file whose result is `Ok` is replaced atomically,
+/// and only if its contents changed. The other files are not modified.
+#[must_use]
+pub fn format_files(files: &[SourceFile], options: &Options) -> Vec<Result<bool>> {
+    write_copies(format_batch(files, read_files(files), options))
+        .into_iter()
+        .zip(files)
+        .map(|(result, file)| {
+            result.and_then(|tempfile| {
+                tempfile.map_or(Ok(false), |tempfile| {
+                    replace(&file.path, tempfile, options).map(|()| true)
+                })
+            })
+        })
+        .collect()
+}
+
+/// Formats `sources`, the contents of `files`, in memory, running `rustfmt` once for all of them
+///
+/// Like [`format_str`], but for many sources at once. Each file's path is used as
+/// [`Options::path`] and its crate root as [`Options::crate_root`]. Returns one result per file.
+/// No file is modified.
+///
+/// # Panics
+///
+/// If `files` and `sources` differ in length.
+#[must_use]
+pub fn format_sources(
+    files: &[SourceFile],
+    sources: &[&str],
+    options: &Options,
+) -> Vec<Result<String>> {
+    assert_eq!(files.len(), sources.len());
+    let contents = sources.iter().map(|source| Ok((*source).to_owned()));
+    format_batch(files, contents.collect(), options)
+        .into_iter()
+        .map(|result| result.map(|formatted| formatted.contents))
+        .collect()
+}
+
+fn read_files(files: &[SourceFile]) -> Vec<Result<String>> {
+    files
+        .iter()
+        .map(|file| {
+            read_to_string(&file.path).failed_to(|| format!("read from `{}`", file.path.display()))
+        })
+        .collect()
+}
+
+/// A temporary copy of a file, prepared for the batch `rustfmt` invocation
+struct Copy {
+    tempfile: NamedTempFile,
+    /// The result of rewriting the file, if it uses `if_chain!`
+    rewritten: Option<Rewritten>,
+}
+
+/// Formats temporary copies of `files`, whose contents are `contents`, with a single `rustfmt`
+/// invocation. Returns, for each file, its original and formatted contents, and its copy.
+///
+/// Files that do not mention `if_chain` are copied as is, i.e., they are not parsed or rewritten.
+/// If a file cannot be rewritten, or if `rustfmt` fails on it, the file is formatted on its own
+/// with [`format_copy`], which reports the failure in detail.
+///
+/// The files that formatting changed are then formatted again, until they stop changing (see
+/// [`reach_fixpoints`]). Each of those passes also uses a single `rustfmt` invocation.
+fn format_batch(
+    files: &[SourceFile],
+    contents: Vec<Result<String>>,
+    options: &Options,
+) -> Vec<Result<Formatted>> {
+    let paths = files.iter().map(|file| &file.path).collect::<Vec<_>>();
+
+    // smoelius: Many files typically share a crate root, so each root is analyzed only once.
+    let mut crate_names = HashMap::new();
+    let names = files
+        .iter()
+        .map(|file| {
+            crate_names
+                .entry(file.crate_root.as_deref())
+                .or_insert_with(|| crate_macro_names(file.crate_root.as_deref(), options))
+                .clone()
+        })
+        .collect::<Vec<_>>();
+
+    // smoelius: Each original is paired with the source to be formatted, if the two differ, i.e.,
+    // if the original was transformed (see `transform`).
+    let originals = contents
+        .into_iter()
+        .zip(&paths)
+        .zip(&names)
+        .map(|((original, path), names)| {
+            original.and_then(|original| {
+                let transformed = transform(path, &original, names, options)?;
+                Ok((original, transformed))
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let sources = originals
+        .iter()
+        .map(|original| {
+            original
+                .as_ref()
+                .ok()
+                .map(|(original, transformed)| transformed.as_deref().unwrap_or(original))
+        })
+        .collect::<Vec<_>>();
+
+    // smoelius: Only the files that are rewritten need to be considered when choosing the marker.
+    let marker = unused_ident(
+        &sources
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|source| uses_if_chain(source, options))
+            .collect::<Vec<_>>(),
+    );
+
+    let copies = paths
+        .iter()
+        .zip(&sources)
+        .zip(&names)
+        .map(|((path, source), names)| prepare_copy(path, (*source)?, &marker, names, options).ok())
+        .collect::<Vec<_>>();
+
+    let batch = paths
+        .iter()
+        .zip(&copies)
+        .filter_map(|(path, copy)| Some((copy.as_ref()?.tempfile.path(), path.as_path())))
+        .collect::<Vec<_>>();
+
+    let mut formatted = rustfmt_batch(&options.rustfmt_args, &batch).into_iter();
+
+    let (copies, progress): (Vec<_>, Vec<_>) = paths
+        .iter()
+        .zip(originals)
+        .zip(copies)
+        .zip(&names)
+        .map(|(((path, original), copy), names)| {
+            let result = original.and_then(|(original, transformed)| {
+                let source = transformed.as_deref().unwrap_or(&original);
+                let (tempfile, progress) = match copy {
+                    Some(copy) if formatted.next() == Some(true) => {
+                        let Pass {
+                            contents, warnings, ..
+                        } = restore_copy(path, source, &copy, &marker, options)?;
+                        warn(&warnings, options);
+                        let progress = Progress {
+                            input: source.to_owned(),
+                            contents,
+                        };
+                        (copy.tempfile, progress)
+                    }
+                    _ => format_copy(path, &original, source, &marker, names, options)?,
+                };
+                Ok(((original, tempfile), progress))
+            });
+            match result {
+                Ok((copy, progress)) => (Some(copy), Ok(progress)),
+                Err(error) => (None, Err(error)),
+            }
+        })
+        .unzip();
+
+    let paths = paths.iter().map(|path| path.as_path()).collect::<Vec<_>>();
+
+    let progress = reach_fixpoints(&paths, progress, options, |indices, sources| {
+        format_passes(
+            &indices.iter().map(|&i| paths[i]).collect::<Vec<_>>(),
+            sources,
+            &indices.iter().map(|&i| &names[i]).collect::<Vec<_>>(),
+            options,
+        )
+    });
+
+    finish_copies(copies, progress)
+}
+
+/// A file's original and formatted contents, and the temporary copy that was formatted
+struct Formatted {
+    original: String,
+    contents: String,
+    tempfile: NamedTempFile,
+}
+
+/// Pairs each file's copy, which is paired with the file's original contents, with the file's
+/// formatted contents
+fn finish_copies(
+    copies: Vec<Option<(String, NamedTempFile)>>,
+    progress: Vec<Result<Progress>>,
+) -> Vec<Result<Formatted>> {
+    copies
+        .into_iter()
+        .zip(progress)
+        .map(|(copy, progress)| {
+            let Progress { contents, .. } = progress?;
+            let (original, tempfile) =
+                copy.expect("a file whose progress is `Ok` should have a copy");
+            Ok(Formatted {
+                original,
+                contents,
+                tempfile,
+            })
+        })
+        .collect()
+}
+
+/// Writes each file's formatted contents to its copy. Returns the copy, or `None` if the formatted
+/// contents are the original ones.
+fn write_copies(formatted: Vec<Result<Formatted>>) -> Vec<Result<Option<NamedTempFile>>> {
+    formatted
+        .into_iter()
+        .map(|formatted| {
+            let Formatted {
+                original,
+                contents,
+                tempfile,
+            } = formatted?;
+            if contents == original {
+                return Ok(None);
+            }
+            write(tempfile.path(), &contents)?;
+            Ok(Some(tempfile))
+        })
+        .collect()
+}
+
+/// Makes a pass (see [`format_pass`]) over each of `sources` with a single `rustfmt` invocation.
+/// `sources` are the results of earlier passes, so `rustfmt` is expected to succeed on them. A
+/// source on which it fails is passed on its own.
+fn format_passes(
+    paths: &[&Path],
+    sources: &[&str],
+    names: &[&MacroNames],
+    options: &Options,
+) -> Vec<Result<Pass>> {
+    let marker = unused_ident(sources);
+
+    let copies = paths
+        .iter()
+        .zip(sources)
+        .zip(names)
+        .map(|((path, source), names)| prepare_copy(path, source, &marker, names, options).ok())
+        .collect::<Vec<_>>();
+
+    let batch = paths
+        .iter()
+        .zip(&copies)
+        .filter_map(|(path, copy)| Some((copy.as_ref()?.tempfile.path(), *path)))
+        .collect::<Vec<_>>();
+
+    let mut formatted = rustfmt_batch(&options.rustfmt_args, &batch).into_iter();
+
+    paths
+        .iter()
+        .zip(sources)
+        .zip(copies)
+        .zip(names)
+        .map(|(((path, source), copy), names)| match copy {
+            Some(copy) if formatted.next() == Some(true) => {
+                restore_copy(path, source, &copy, &marker, options)
+            }
+            _ => format_pass(path, source, names, options),
+        })
+        .collect()
+}
+
+/// Returns the names by which the `if_chain` crate's macro can be invoked in the crate whose root
+/// is `crate_root`. If the root cannot be read or parsed, only the default names and
+/// [`Options::macro_names`] are used; the root's own errors are reported when it is formatted.
+fn crate_macro_names(crate_root: Option<&Path>, options: &Options) -> MacroNames {
+    let names = MacroNames::new(&options.macro_names);
+    crate_root
+        .and_then(|crate_root| read_to_string(crate_root).ok())
+        .and_then(|contents| syn::parse_file(&contents).ok())
+        .map_or_else(|| names.clone(), |file| names.analyze_crate_root(&file))
+}
+
+/// Rewrites nested `if` expressions into `if_chain!` invocations, if [`Options::suggest_if_chain`]
+/// is set, and migrates `if_chain!` invocations to let chains, if
+/// [`Options::migrate_to_let_chains`] is set. Returns `None` if the contents would not change.
+fn transform(
+    path: &Path,
+    original: &str,
+    names: &MacroNames,
+    options: &Options,
+) -> Result<Option<String>> {
+    let mut contents = original.to_owned();
+
+    if options.suggest_if_chain {
+        contents = suggest_if_chain(&contents, names);
+    }
+
+    if options.migrate_to_let_chains && uses_if_chain(&contents, options) {
+        // smoelius: `style_edition` does not affect how `rustfmt` parses, so only `edition`
+        // matters.
+        let edition = edition(path, &options.rustfmt_args);
+        ensure!(
+            edition.parse::<u32>().is_ok_and(|edition| edition >= 2024),
+            "failed to migrate `{}` to let chains: let chains require edition 2024, but \
+             `rustfmt` would parse it as edition {edition}; pass `--edition 2024` or set \
+             `edition = \"2024\"` in `rustfmt.toml`",
+            path.display()
+        );
+        let (migrated, warnings) = migrate_to_let_chains(path, &contents, names);
+        warn(&warnings, options);
+        contents = migrated;
+    }
+
+    Ok(Some(contents).filter(|contents| contents != original))
+}
+
+/// Returns true if `contents` might invoke the `if_chain` crate's macro. Aliases (e.g., `use
+/// if_chain::if_chain as chain;`) require the crate's name to appear, so only the names given with
+/// [`Options::macro_names`] need to be checked in addition to it.
+fn uses_if_chain(contents: &str, options: &Options) -> bool {
+    contents.contains("if_chain")
+        || options.macro_names.iter().any(|name| {
+            name.rsplit("::")
+                .next()
+                .is_some_and(|name| contents.contains(name))
+        })
+}
+
+/// Creates a temporary copy of the file at `path` for the batch `rustfmt` invocation. If the file
+/// uses `if_chain!`, the copy holds the rewritten contents.
+fn prepare_copy(
+    path: &Path,
+    original: &str,
+    marker: &Ident,
+    names: &MacroNames,
+    options: &Options,
+) -> Result<Copy> {
+    let tempfile = tempfile_beside(path)?;
+
+    let rewritten = if uses_if_chain(original, options) {
+        Some(rewrite_if_chain(
+            path,
+            original,
+            marker,
+            names,
+            &skip_macro_invocations(path, &options.rustfmt_args),
+        )?)
+    } else {
+        None
+    };
+
+    write(
+        tempfile.path(),
+        rewritten
+            .as_ref()
+            .map_or(original, |rewritten| &rewritten.contents),
+    )?;
+
+    Ok(Copy {
+        tempfile,
+        rewritten,
+    })
+}
+
+/// Undoes the rewrites in a copy formatted by a batch `rustfmt` invocation. `source` is the copy's
+/// contents before they were rewritten.
+fn restore_copy(
+    path: &Path,
+    source: &str,
+    copy: &Copy,
+    marker: &Ident,
+    options: &Options,
+) -> Result<Pass> {
+    let formatted = read_to_string(copy.tempfile.path())
+        .failed_to(|| format!("read from `{}`", copy.tempfile.path().display()))?;
+
+    let Some(Rewritten {
+        contents: rewritten,
+        invocations,
+        warnings,
+    }) = &copy.rewritten
+    else {
+        return Ok(Pass {
+            contents: formatted,
+            invocations: Vec::new(),
+            warnings: Vec::new(),
+        });
+    };
+
+    let restored = restored(invocations, options);
+
+    let contents = restore_if_chain(path, &formatted, marker, &restored)?;
+
+    if !options.no_verify {
+        verify(path, source, rewritten, marker, invocations)?;
+        verify(path, &contents, &formatted, marker, &restored)?;
+    }
+
+    Ok(Pass {
+        contents,
+        invocations: invocations.clone(),
+        warnings: warnings.clone(),
+    })
+}
+
+/// Formats a temporary copy of the file at `path`, whose contents are `original`, on its own. The
+/// copy holds `source`, i.e., `original`, possibly transformed (see [`transform`]). Unlike
+/// [`format_batch`], `rustfmt` is first run on the unmodified copy, so that failures caused by the
+/// original source can be told apart from ones caused by the rewrites. Returns the copy, along with
+/// the formatted contents, which are `original` if `rustfmt` failed and the failure was a warning.
+fn format_copy(
+    path: &Path,
+    original: &str,
+    source: &str,
+    marker: &Ident,
+    names: &MacroNames,
+    options: &Options,
+) -> Result<(NamedTempFile, Progress)> {
+    let tempfile = tempfile_beside(path)?;
+
+    write(tempfile.path(), source)?;
+
+    if let Err(error) = rustfmt(&options.rustfmt_args, tempfile.path(), path) {
+        if options.preformat_failure_is_warning {
+            options.on_warning.warn(&error.to_string());
+            let progress = Progress {
+                input: original.to_owned(),
+                contents: original.to_owned(),
+            };
+            return Ok((tempfile, progress));
+        }
+        return Err(error);
+    }
+
+    let preformatted = read_to_string(tempfile.path())
+        .failed_to(|| format!("read from `{}`", tempfile.path().display()))?;
+
+    let Rewritten {
+        contents: rewritten,
+        invocations,
+        warnings,
+    } = rewrite_if_chain(
+        path,
+        &preformatted,
+        marker,
+        names,
+        &skip_macro_invocations(path, &options.rustfmt_args),
+    )?;
+
+    warn(&warnings, options);
+
+    write(tempfile.path(), &rewritten)?;
+
+    rustfmt(&options.rustfmt_args, tempfile.path(), path)?;
+
+    let formatted = read_to_string(tempfile.path())
+        .failed_to(|| format!("read from `{}`", tempfile.path().display()))?;
+
+    let restored = restored(&invocations, options);
+
+    let contents = restore_if_chain(path, &formatted, marker, &restored)?;
+
+    if !options.no_verify {
+        verify(path, &preformatted, &rewritten, marker, &invocations)?;
+        verify(path, &contents, &formatted, marker, &restored)?;
+    }
+
+    let progress = Progress {
+        input: preformatted,
+        contents,
+    };
+
+    Ok((tempfile, progress))
+}
+
+/// The most times the rewrite, `rustfmt`, restore round trip is made on a file before
+/// [`reach_fixpoints`] gives up
+const MAX_PASSES: usize = 4;
+
+/// The result of one round trip through [`format_pass`]
+struct Pass {
+    contents: String,
+    /// The invocations rewritten during the pass
+    invocations: Vec<Invocation>,
+    warnings: Vec<String>,
+}
+
+/// A file's contents after a pass, and the contents the pass was made on
+struct Progress {
+    input: String,
+    contents: String,
+}
+
+/// Rewrites the `if_chain!` invocations in `source`, formats the result with `rustfmt`, and undoes
+/// the rewrites
+fn format_pass(path: &Path, source: &str, names: &MacroNames, options: &Options) -> Result<Pass> {
+    let marker = unused_ident(&[source]);
+
+    let Rewritten {
+        contents: rewritten,
+        invocations,
+        warnings,
+    } = rewrite_if_chain(
+        path,
+        source,
+        &marker,
+        names,
+        &skip_macro_invocations(path, &options.rustfmt_args),
+    )?;
+
+    let formatted = rustfmt_stdin(&options.rustfmt_args, path, &rewritten)?;
+
+    let restored = restored(&invocations, options);
+
+    let contents = restore_if_chain(path, &formatted, &marker, &restored)?;
+
+    if !options.no_verify {
+        verify(path, source, &rewritten, &marker, &invocations)?;
+        verify(path, &contents, &formatted, &marker, &restored)?;
+    }
+
+    Ok(Pass {
+        contents,
+        invocations,
+        warnings,
+    })
+}
+
+/// Makes further passes over the files at `paths`, whose first passes resulted in `progress`, until
+/// their contents stop changing, so that formatting them again would not change them. `pass` is
+/// given the indices and contents of the files that need another pass, and returns one result for
+/// each. Warnings are not repeated, since the first passes reported them.
+///
+/// A file whose first pass changed nothing, or whose contents do not use `if_chain!`, needs no
+/// further passes. Passing the same contents through the round trip gives the same result, and
+/// `rustfmt` on its own is idempotent. A file whose contents still change after [`MAX_PASSES`]
+/// passes results in an error naming the line that keeps changing, and the invocation containing
+/// it.
+fn reach_fixpoints(
+    paths: &[&Path],
+    mut progress: Vec<Result<Progress>>,
+    options: &Options,
+    mut pass: impl FnMut(&[usize], &[&str]) -> Vec<Result<Pass>>,
+) -> Vec<Result<Progress>> {
+    for n_passes in 2..=MAX_PASSES {
+        let indices = progress
+            .iter()
+            .enumerate()
+            .filter_map(|(i, progress)| {
+                let progress = progress.as_ref().ok()?;
+                (progress.contents != progress.input && uses_if_chain(&progress.contents, options))
+                    .then_some(i)
+            })
+            .collect::<Vec<_>>();
+
+        if indices.is_empty() {
+            break;
+        }
+
+        let sources = indices
+            .iter()
+            .filter_map(|&i| progress[i].as_ref().ok())
+            .map(|progress| progress.contents.as_str())
+            .collect::<Vec<_>>();
+
+        let passes = pass(&indices, &sources);
+
+        for (i, next) in indices.into_iter().zip(passes) {
+            let error = match (&mut progress[i], next) {
+                (Ok(current), Ok(next))
+                    if next.contents != current.contents && n_passes == MAX_PASSES =>
+                {
+                    not_idempotent(paths[i], &current.contents, &next)
+                }
+                (Ok(current), Ok(next)) => {
+                    current.input = take(&mut current.contents);
+                    current.contents = next.contents;
+                    continue;
+                }
+                (_, Err(error)) => error,
+                (Err(_), Ok(_)) => continue,
+            };
+            progress[i] = Err(error);
+        }
+    }
+
+    progress
+}
+
+/// Returns an error describing the first line that `next` changed in `contents`
+fn not_idempotent(path: &Path, contents: &str, next: &Pass) -> Error {
+    let line = contents
+        .lines()
+        .zip(next.contents.lines())
+        .take_while(|(left, right)| left == right)
+        .count()
+        + 1;
+
+    // smoelius: Invocations are recorded before the ones nested within them, so the last one that
+    // contains `line` is the innermost.
+    let within = next
+        .invocations
+        .iter()
+        .rev()
+        .find(|invocation| (invocation.first_line..=invocation.last_line).contains(&line))
+        .map(|invocation| {
+            format!(
+                ", within the `{}` invocation at line {}",
+                invocation.path, invocation.first_line
+            )
+        })
+        .unwrap_or_default();
+
+    anyhow!(
+        "failed to format `{}` idempotently: line {line} of the formatted source still changed \
+         after {MAX_PASSES} passes{within}",
+        path.display()
+    )
+}
+
+fn warn(warnings: &[String], options: &Options) {
+    for warning in warnings {
+        options.on_warning.warn(warning);
+    }
+}
+
+/// Returns `invocations` as they are to be restored. See [`Options::convert_to_braces`].
+fn restored(invocations: &[Invocation], options: &Options) -> Vec<Invocation> {
+    invocations
+        .iter()
+        .map(|invocation| {
+            if options.convert_to_braces {
+                invocation.braced()
+            } else {
+                invocation.clone()
+            }
+        })
+        .collect()
+}
+
+/// Creates a temporary file in `path`'s directory, so that `rustfmt` finds the same configuration
+/// for the temporary file that it would for `path`
+fn tempfile_beside(path: &Path) -> Result<NamedTempFile> {
+    let dir = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    Builder::new()
+        .prefix(".rustfmt_if_chain")
+        .suffix(".rs")
+        .tempfile_in(dir)
+        .failed_to(|| format!("create temporary file in `{}`", dir.display()))
+}
+
+/// Atomically replaces the file at `path` with `tempfile`, keeping `path`'s permissions. See
+/// [`Options::backup`].
+fn replace(path: &Path, tempfile: NamedTempFile, options: &Options) -> Result<()> {
+    if options.backup {
+        let backup = path.with_extension("bk");
+        copy(path, &backup)
+            .failed_to(|| format!("copy `{}` to `{}`", path.display(), backup.display()))?;
+    }
+    let permissions = metadata(path)
+        .failed_to(|| format!("get metadata of `{}`", path.display()))?
+        .permissions();
+    set_permissions(tempfile.path(), permissions)
+        .failed_to(|| format!("set permissions of `{}`", tempfile.path().display()))?;
+    tempfile
+        .persist(path)
+        .failed_to(|| format!("replace `{}`", path.display()))?;
+
+    Ok(())
+}
+
+fn write(path: &Path, contents: &str) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .truncate(true)
+        .write(true)
+        .open(path)
+        .failed_to(|| format!("open `{}`", path.display()))?;
+    file.write_all(contents.as_bytes())
+        .failed_to(|| format!("write to `{}`", path.display()))?;
+
+    Ok(())
+}
+
+#[test]
+fn reach_fixpoints_repeats_passes() {
+    let paths = [Path::new("converges.rs"), Path::new("alternates.rs")];
+    let progress = |contents: &str| {
+        Ok(Progress {
+            input: String::new(),
+            contents: contents.to_owned(),
+        })
+    };
+
+    // smoelius: The first file loses a space with each pass. The second alternates between two
+    // contents.
+    let mut progress = reach_fixpoints(
+        &paths,
+        vec![
+            progress("if_chain! {   }\n"),
+            progress("if_chain! {\n    one\n}\n"),
+        ],
+        &Options::default(),
+        |_, sources| {
+            sources
+                .iter()
+                .map(|source| {
+                    let contents = if source.contains("one") {
+                        source.replace("one", "two")
+                    } else if source.contains("two") {
+                        source.replace("two", "one")
+                    } else {
+                        source.replacen("  ", " ", 1)
+                    };
+                    Ok(Pass {
+                        contents,
+                        invocations: vec![Invocation {
+                            path: String::from("if_chain!"),
+                            delimiter: proc_macro2::Delimiter::Brace,
+                            semi: false,
+                            first_line: 1,
+                            last_line: 3,
+                        }],
+                        warnings: Vec::new(),
+                    })
+                })
+                .collect()
+        },
+    );
+
+    assert_eq!(
+        "failed to format `alternates.rs` idempotently: line 2 of the formatted source still \
+         changed after 4 passes, within the `if_chain!` invocation at line 1",
+        progress.remove(1).err().unwrap().to_string()
+    );
+    assert_eq!("if_chain! { }\n", progress.remove(0).unwrap().contents);
+}
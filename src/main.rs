@@ -1,302 +1,885 @@
 use anyhow::{Result, anyhow, ensure};
-use if_chain::if_chain;
-use proc_macro2::{Span, TokenStream, TokenTree};
-use quote::{ToTokens, quote};
-use rewriter::{Backup, Rewriter};
-use sedregex::find_and_replace;
+use jobserver::Client;
+use rustfmt_if_chain::{
+    Exclusions, FailedTo, Options, SourceFile, WarningHandler, format_files, format_sources,
+    format_str, module_files, rust_files,
+};
+use similar::TextDiff;
 use std::{
+    collections::{HashMap, HashSet},
     env,
-    fs::{OpenOptions, read_to_string},
-    io::Write,
-    path::Path,
+    fs::read_to_string,
+    io::{Read, stdin},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
     process::{Command, exit},
-};
-use syn::{
-    ExprMacro, Ident, ItemMacro, Macro, MacroDelimiter, StmtMacro, parse_file,
-    spanned::Spanned,
-    visit::{Visit, visit_expr_macro, visit_item_macro, visit_stmt_macro},
+    thread::available_parallelism,
 };
 
-mod failed_to;
-use failed_to::FailedTo;
+mod git;
+
+mod parallel;
+
+mod report;
+use report::{Checked, Format, report};
+
+#[allow(clippy::struct_excessive_bools)]
+struct Args {
+    rustfmt_args: Vec<String>,
+    paths: Vec<String>,
+    changed_since: Option<String>,
+    staged: bool,
+    index: bool,
+    install_hook: bool,
+    check: bool,
+    preformat_failure_is_warning: bool,
+    no_verify: bool,
+    convert_to_braces: bool,
+    migrate_to_let_chains: bool,
+    suggest_if_chain: bool,
+    macro_names: Vec<String>,
+    excludes: Vec<String>,
+    emit: Emit,
+    files_with_diff: bool,
+    backup: bool,
+    verbose: bool,
+    stdin_filepath: Option<PathBuf>,
+    jobs: usize,
+}
 
-fn main() -> Result<()> {
-    let (args, paths, preformat_failure_is_warning) = process_args();
+/// Where the formatted source goes, selected with `--emit`
+#[derive(Clone, Copy)]
+enum Emit {
+    /// The source files are modified (the default)
+    Files,
+    /// The formatted source is written to stdout, and no file is modified
+    Stdout,
+    /// A report is written to stdout, and no file is modified
+    Report(Format),
+}
 
-    if paths.is_empty() {
-        return rustfmt(&args, None);
+/// `rustfmt` arguments that cause it to print information rather than format anything
+const INFORMATIONAL_ARGS: &[&str] = &["-V", "--version", "--print-config", "--help=config"];
+
+fn main() -> Result<()> {
+    let client = parallel::jobserver();
+
+    let Args {
+        rustfmt_args,
+        paths,
+        changed_since,
+        staged,
+        index,
+        install_hook,
+        check,
+        preformat_failure_is_warning,
+        no_verify,
+        convert_to_braces,
+        migrate_to_let_chains,
+        suggest_if_chain,
+        macro_names,
+        excludes,
+        emit,
+        files_with_diff,
+        backup,
+        verbose,
+        stdin_filepath,
+        jobs,
+    } = process_args()?;
+
+    if install_hook {
+        let path = git::install_hook()?;
+        println!("Installed pre-commit hook at `{}`", path.display());
+        return Ok(());
     }
 
-    for path in paths {
-        let path = Path::new(&path);
+    let changed = changed_since.is_some() || staged || index;
 
-        if let Err(error) = rustfmt(&args, Some(path)) {
-            if preformat_failure_is_warning {
-                eprintln!("Warning: {error}");
-                continue;
-            }
-            return Err(error);
+    if paths.is_empty()
+        && !changed
+        && rustfmt_args
+            .iter()
+            .any(|arg| INFORMATIONAL_ARGS.contains(&arg.as_str()))
+    {
+        let mut command = Command::new("rustfmt");
+        command.args(&rustfmt_args);
+        let status = command
+            .status()
+            .failed_to(|| format!("get status of {command:?}"))?;
+        ensure!(status.success(), "{command:?} failed");
+        return Ok(());
+    }
+
+    let mut options = Options::default();
+    options.rustfmt_args = rustfmt_args;
+    options.preformat_failure_is_warning = preformat_failure_is_warning;
+    options.no_verify = no_verify;
+    options.macro_names = macro_names;
+    options.convert_to_braces = convert_to_braces;
+    options.migrate_to_let_chains = migrate_to_let_chains;
+    options.suggest_if_chain = suggest_if_chain;
+    options.backup = backup;
+    options.on_warning = WarningHandler::new(|warning| eprintln!("Warning: {warning}"));
+
+    if paths.is_empty() && !changed {
+        if !format_stdin(stdin_filepath, check, emit, options)? {
+            exit(1);
         }
+        return Ok(());
+    }
 
-        let mut backup = Backup::new(path).failed_to(|| format!("backup `{}`", path.display()))?;
+    let mut exclusions = Exclusions::new(&excludes, &options.rustfmt_args)?;
 
-        let marker = rewrite_if_chain(path)?;
+    let files = if changed {
+        changed_source_files(changed_since.as_deref(), staged || index, &mut exclusions)?
+    } else {
+        all_module_files(&paths, &mut exclusions)?
+    };
 
-        rustfmt(&args, Some(path))?;
+    // smoelius: Given `-v`, `rustfmt` would print the names of the temporary files, so the names of
+    // the source files are printed here instead. They are written to stderr so that they do not
+    // mix with diffs or reports.
+    if verbose {
+        for file in &files {
+            eprintln!("Formatting {}", file.path.display());
+        }
+    }
+
+    let client = client.as_ref();
 
-        restore_if_chain(path, &marker)?;
+    let success = match emit {
+        Emit::Report(format) => report_files(&files, format, check, &options, index, jobs, client),
+        Emit::Stdout => print_files(&files, &options, index, jobs, client),
+        Emit::Files if check => check_files(&files, files_with_diff, &options, index, jobs, client),
+        Emit::Files if index => format_index(&files, files_with_diff, &options, jobs, client),
+        Emit::Files => format_in_place(&files, files_with_diff, &options, jobs, client),
+    };
 
-        backup
-            .disable()
-            .failed_to(|| format!("disable `{}` backup", path.display()))?;
+    if !success {
+        exit(1);
     }
 
     Ok(())
 }
 
-#[allow(clippy::case_sensitive_file_extension_comparisons)]
-fn process_args() -> (Vec<String>, Vec<String>, bool) {
-    let mut args = Vec::new();
-    let mut paths = Vec::new();
-    let mut preformat_failure_is_warning = false;
-    for arg in env::args().skip(1) {
-        if arg == "--help" || arg == "-h" {
-            usage();
-        } else if arg == "--preformat-failure-is-warning" {
-            preformat_failure_is_warning = true;
-        } else if arg.to_lowercase().ends_with(".rs") {
-            paths.push(arg);
-        } else {
-            args.push(arg);
-        }
-    }
-    (args, paths, preformat_failure_is_warning)
+/// Divides `files` into one batch per job, applies `f` to each batch, and returns `f`'s results in
+/// the order of `files`. `f` must return one result per file of its batch.
+fn in_batches<R, F>(files: &[SourceFile], jobs: usize, client: Option<&Client>, f: F) -> Vec<R>
+where
+    R: Send,
+    F: Fn(&[SourceFile]) -> Vec<R> + Sync,
+{
+    // smoelius: Each batch is formatted with one `rustfmt` invocation. The files are dealt out
+    // round-robin so that files using `if_chain!`, which take longer, tend to be spread out.
+    let n_batches = jobs.min(files.len());
+    let batches = (0..n_batches)
+        .map(|i| {
+            files
+                .iter()
+                .skip(i)
+                .step_by(n_batches)
+                .cloned()
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut batch_results = parallel::map(jobs, client, &batches, |batch| f(batch).into_iter());
+
+    (0..files.len())
+        .map(|i| {
+            batch_results[i % n_batches]
+                .next()
+                .expect("each batch should have a result for each of its files")
+        })
+        .collect()
 }
 
-const USAGE: &str = "\
-Usage: rustfmt_if_chain [ARGS]
+/// Formats `files` in place. If `files_with_diff` is true, the names of the modified files are
+/// printed. Returns `false` if any file cannot be formatted.
+fn format_in_place(
+    files: &[SourceFile],
+    files_with_diff: bool,
+    options: &Options,
+    jobs: usize,
+    client: Option<&Client>,
+) -> bool {
+    let results = in_batches(files, jobs, client, |batch| format_files(batch, options));
+
+    let mut success = true;
+
+    for (file, result) in files.iter().zip(results) {
+        match result {
+            Ok(modified) => {
+                if modified && files_with_diff {
+                    println!("{}", file.path.display());
+                }
+            }
+            Err(error) => {
+                eprintln!("Error: {error:?}");
+                success = false;
+            }
+        }
+    }
 
-Arguments ending with `.rs` are considered source files and are
-formatted. All other arguments are forwarded to `rustfmt`, with
-one exception.
+    success
+}
 
-The one argument not forwarded to `rustfmt` is
-`--preformat-failure-is-warning`. If this option is passed and
-`rustfmt` fails on an unmodified source file, a warning results
-instead of an error.\
-";
+/// Formats the staged contents of `files` in memory and writes the results to the index. Each
+/// change is applied to the working tree too, if it applies cleanly. If `files_with_diff` is true,
+/// the names of the modified files are printed. Returns `false` if any file cannot be formatted.
+fn format_index(
+    files: &[SourceFile],
+    files_with_diff: bool,
+    options: &Options,
+    jobs: usize,
+    client: Option<&Client>,
+) -> bool {
+    let results = check_paths(files, options, true, jobs, client);
+
+    let mut success = true;
+
+    for (file, result) in files.iter().zip(results) {
+        let path = &file.path;
+        let result = result.and_then(|(original, contents)| {
+            if original == contents {
+                return Ok(false);
+            }
+            git::update_index(path, &contents)?;
+            if !git::apply_to_working_tree(path, &original, &contents)? {
+                eprintln!(
+                    "Warning: formatting of the staged contents of `{}` does not apply cleanly to \
+                     the working tree; only the index was modified",
+                    path.display()
+                );
+            }
+            Ok(true)
+        });
+        match result {
+            Ok(modified) => {
+                if modified && files_with_diff {
+                    println!("{}", path.display());
+                }
+            }
+            Err(error) => {
+                eprintln!("Error: {error:?}");
+                success = false;
+            }
+        }
+    }
 
-fn usage() -> ! {
-    println!("{USAGE}");
-    exit(0);
+    success
 }
 
-fn rewrite_if_chain(path: &Path) -> Result<Ident> {
-    let contents = read_to_string(path).failed_to(|| format!("read from `{}`", path.display()))?;
+/// Formats `files`' contents in memory and prints a unified diff for each one that would change,
+/// or only its name if `files_with_diff` is true. No file is modified. If `index` is true, the
+/// files' staged contents are formatted. Returns `false` if any file is not formatted or cannot be
+/// checked.
+fn check_files(
+    files: &[SourceFile],
+    files_with_diff: bool,
+    options: &Options,
+    index: bool,
+    jobs: usize,
+    client: Option<&Client>,
+) -> bool {
+    let results = check_paths(files, options, index, jobs, client);
+
+    let mut formatted = true;
+
+    // smoelius: Diffs and errors are printed in the order of `files`, regardless of the order in
+    // which the files were checked.
+    for (file, result) in files.iter().zip(results) {
+        match result {
+            Ok((original, contents)) if files_with_diff => {
+                if original != contents {
+                    println!("{}", file.path.display());
+                    formatted = false;
+                }
+            }
+            Ok((original, contents)) => {
+                formatted &= !print_diff(&file.path, &original, &contents);
+            }
+            Err(error) => {
+                eprintln!("Error: {error:?}");
+                formatted = false;
+            }
+        }
+    }
 
-    let marker = unused_ident(&contents);
+    formatted
+}
 
-    let file = parse_file(&contents)
-        .map_err(|error| anyhow!("{} at {:?}", error, error.span().start()))
-        .failed_to(|| format!("parse `{}`", path.display()))?;
+/// Like [`check_files`], but prints a report in `format` instead of diffs. Returns `false` if any
+/// file cannot be checked, or if `check` is true and any file is not formatted.
+fn report_files(
+    files: &[SourceFile],
+    format: Format,
+    check: bool,
+    options: &Options,
+    index: bool,
+    jobs: usize,
+    client: Option<&Client>,
+) -> bool {
+    let results = check_paths(files, options, index, jobs, client);
+
+    let mut success = true;
+    let mut checked = Vec::new();
+
+    for (file, result) in files.iter().zip(&results) {
+        match result {
+            Ok((original, formatted)) => {
+                success &= !check || original == formatted;
+                checked.push(Checked {
+                    path: &file.path,
+                    original,
+                    formatted,
+                });
+            }
+            Err(error) => {
+                eprintln!("Error: {error:?}");
+                success = false;
+            }
+        }
+    }
 
-    let mut visitor = RewriteVisitor {
-        rewriter: Rewriter::new(&contents),
-        marker: &marker,
-    };
+    print!("{}", report(format, &checked));
 
-    visitor.visit_file(&file);
+    success
+}
 
-    let mut file = OpenOptions::new()
-        .truncate(true)
-        .write(true)
-        .open(path)
-        .failed_to(|| format!("open `{}`", path.display()))?;
-    file.write_all(visitor.rewriter.contents().as_bytes())
-        .failed_to(|| format!("write to `{}`", path.display()))?;
+/// Formats `files`' contents (or with `index`, their staged contents) in memory and prints the
+/// results, in order, to stdout. No file is modified. Returns `false` if any file cannot be
+/// formatted.
+fn print_files(
+    files: &[SourceFile],
+    options: &Options,
+    index: bool,
+    jobs: usize,
+    client: Option<&Client>,
+) -> bool {
+    let results = check_paths(files, options, index, jobs, client);
+
+    // smoelius: Like `rustfmt`, precede each file's contents with its path, unless `-q` was given.
+    let quiet = options
+        .rustfmt_args
+        .iter()
+        .any(|arg| arg == "-q" || arg == "--quiet");
+
+    let mut success = true;
+
+    for (file, result) in files.iter().zip(results) {
+        match result {
+            Ok((_, contents)) => {
+                if !quiet {
+                    print!("{}:\n\n", file.path.display());
+                }
+                print!("{contents}");
+            }
+            Err(error) => {
+                eprintln!("Error: {error:?}");
+                success = false;
+            }
+        }
+    }
 
-    Ok(marker)
+    success
 }
 
-fn unused_ident(contents: &str) -> Ident {
-    let mut i = 0;
-    loop {
-        let x = format!("x{i}");
-        if !contents.contains(&x) {
-            return Ident::new(&x, Span::call_site());
+/// Returns the files of the modules reachable from each of `paths`' crate roots, without
+/// duplicates or files that `exclusions` exclude. Each file's crate root is taken to be the root
+/// from which it was reached. See [`crate_roots`] and [`module_files`].
+fn all_module_files(paths: &[String], exclusions: &mut Exclusions) -> Result<Vec<SourceFile>> {
+    let mut files = Vec::new();
+    let mut seen = HashSet::new();
+
+    for root in crate_roots(paths, exclusions)? {
+        for path in module_files(&root)? {
+            let canonical = path
+                .canonicalize()
+                .failed_to(|| format!("canonicalize `{}`", path.display()))?;
+            if seen.insert(canonical) && !exclusions.is_excluded(&path)? {
+                files.push(SourceFile {
+                    path,
+                    crate_root: Some(root.clone()),
+                });
+            }
         }
-        i += 1;
     }
-}
 
-struct RewriteVisitor<'rewrite> {
-    rewriter: Rewriter<'rewrite>,
-    marker: &'rewrite Ident,
+    Ok(files)
 }
 
-impl Visit<'_> for RewriteVisitor<'_> {
-    fn visit_item_macro(&mut self, item_macro: &ItemMacro) {
-        if self.rewrite_macro(&item_macro.mac, true) {
-            return;
+/// Returns the `.rs` files that changed (see [`git::changed_files`]), without files that
+/// `exclusions` exclude. Each file is formatted on its own, i.e., the files of its modules are
+/// formatted only if they changed too, and its crate root is unknown.
+fn changed_source_files(
+    since: Option<&str>,
+    staged: bool,
+    exclusions: &mut Exclusions,
+) -> Result<Vec<SourceFile>> {
+    let mut files = Vec::new();
+
+    for path in git::changed_files(since, staged)? {
+        if !exclusions.is_excluded(&path)? {
+            files.push(SourceFile {
+                path,
+                crate_root: None,
+            });
         }
-        visit_item_macro(self, item_macro);
     }
 
-    fn visit_stmt_macro(&mut self, stmt_macro: &StmtMacro) {
-        if self.rewrite_macro(&stmt_macro.mac, true) {
-            return;
+    Ok(files)
+}
+
+/// Returns `paths` with each directory replaced by the crate roots among the `.rs` files within it
+/// (see [`rust_files`]). Such a file is taken to be a crate root unless it is the file of a module
+/// reachable from another. Files that `exclusions` exclude are skipped, as are crate roots whose
+/// modules cannot be resolved, with a warning.
+fn crate_roots(paths: &[String], exclusions: &mut Exclusions) -> Result<Vec<PathBuf>> {
+    let mut roots = Vec::new();
+
+    for path in paths.iter().map(Path::new) {
+        if !path.is_dir() {
+            roots.push(path.to_path_buf());
+            continue;
         }
-        visit_stmt_macro(self, stmt_macro);
-    }
 
-    fn visit_expr_macro(&mut self, expr_macro: &ExprMacro) {
-        if self.rewrite_macro(&expr_macro.mac, false) {
-            return;
+        let mut candidates = Vec::new();
+        for file in rust_files(path)? {
+            // smoelius: An excluded file's modules are not resolved, since they might not exist,
+            // e.g., `#[cfg(any())] mod missing;`.
+            if !exclusions.is_excluded(&file)? {
+                candidates.push(file);
+            }
         }
-        visit_expr_macro(self, expr_macro);
-    }
-}
 
-impl RewriteVisitor<'_> {
-    fn rewrite_macro(&mut self, mac: &Macro, is_item: bool) -> bool {
-        if let Some((span, tokens)) = match_if_chain(mac) {
-            let marker = self.marker;
-            self.rewrite(
-                span,
-                &if is_item {
-                    quote! { fn #marker() }
-                } else {
-                    quote! { |#marker| }
+        // smoelius: Resolving a module's file as though it were a crate root would look for its
+        // submodules in the wrong directory, e.g., `src/foo.rs`'s `mod bar;` in `src` rather than
+        // `src/foo`. So the modules reachable from `lib.rs` and `main.rs` files are found first,
+        // and only the files that are not among them are resolved as crate roots.
+        let (conventional, others) = candidates
+            .iter()
+            .partition::<Vec<_>, _>(|file| is_conventional_root(file));
+        let mut modules = HashSet::new();
+        let mut resolved = HashMap::new();
+        for file in conventional.into_iter().chain(others) {
+            let canonical = file
+                .canonicalize()
+                .failed_to(|| format!("canonicalize `{}`", file.display()))?;
+            if modules.contains(&canonical) {
+                continue;
+            }
+            let result = module_files(file);
+            if let Ok(files) = &result {
+                for module in files.iter().skip(1) {
+                    modules.insert(module.canonicalize().unwrap_or_else(|_| module.clone()));
                 }
-                .to_string(),
-            );
-            self.rewrite_tokens(tokens);
-            true
-        } else {
-            false
+            }
+            resolved.insert(canonical, result);
         }
-    }
 
-    fn rewrite_tokens(&mut self, tokens: &TokenStream) {
-        let mut iter = tokens.clone().into_iter().peekable();
-        let mut curr_ends_let = if let Some(TokenTree::Ident(ident)) = iter.peek() {
-            ident == "let"
-        } else {
-            false
-        };
-        while let Some(curr) = iter.next() {
-            match (&curr, iter.peek()) {
-                (TokenTree::Punct(punct), Some(TokenTree::Ident(next)))
-                    if punct.as_char() == ';'
-                        && ["if", "let", "then"].contains(&next.to_string().as_str()) =>
-                {
-                    let marker = self.marker;
-                    if !curr_ends_let {
-                        self.rewrite(
-                            curr.span(),
-                            &quote! { { #marker; } }.to_token_stream().to_string(),
-                        );
-                    }
-                    if *next == "then" {
-                        self.rewrite(
-                            next.span(),
-                            &quote! { if #marker }.to_token_stream().to_string(),
-                        );
-                        return;
-                    }
-                    curr_ends_let = *next == "let";
+        // smoelius: A file resolved before the module that includes it was found is not a crate
+        // root, even if it was resolved successfully.
+        for file in candidates {
+            let canonical = file
+                .canonicalize()
+                .failed_to(|| format!("canonicalize `{}`", file.display()))?;
+            if modules.contains(&canonical) {
+                continue;
+            }
+            match resolved.remove(&canonical) {
+                Some(Ok(_)) => roots.push(file),
+                Some(Err(error)) => {
+                    eprintln!("Warning: skipping `{}`: {error}", file.display());
                 }
-                (_, _) => {}
+                None => {}
             }
         }
-        panic!("`if_chain!` without `then`");
     }
 
-    fn rewrite(&mut self, span: Span, replacement: &str) {
-        self.rewriter.rewrite(&span, replacement);
+    Ok(roots)
+}
+
+/// Returns true if `file` is named `lib.rs` or `main.rs`
+fn is_conventional_root(file: &Path) -> bool {
+    file.file_name()
+        .is_some_and(|name| name == "lib.rs" || name == "main.rs")
+}
+
+/// Formats `files`' contents (or with `index`, their staged contents) in memory, in batches (see
+/// [`in_batches`]). Returns the original and formatted contents of each file.
+fn check_paths(
+    files: &[SourceFile],
+    options: &Options,
+    index: bool,
+    jobs: usize,
+    client: Option<&Client>,
+) -> Vec<Result<(String, String)>> {
+    in_batches(files, jobs, client, |batch| {
+        let originals = batch
+            .iter()
+            .map(|file| read_original(&file.path, index))
+            .collect::<Vec<_>>();
+
+        let (readable, sources): (Vec<_>, Vec<_>) = batch
+            .iter()
+            .zip(&originals)
+            .filter_map(|(file, original)| Some((file.clone(), original.as_deref().ok()?)))
+            .unzip();
+
+        let mut formatted = format_sources(&readable, &sources, options).into_iter();
+
+        originals
+            .into_iter()
+            .map(|original| {
+                let original = original?;
+                let contents = formatted
+                    .next()
+                    .expect("each readable file should have a result")?;
+                Ok((original, contents))
+            })
+            .collect()
+    })
+}
+
+/// Returns the contents of the file at `path`, or with `index`, its staged contents
+fn read_original(path: &Path, index: bool) -> Result<String> {
+    if index {
+        git::staged_contents(path)
+    } else {
+        read_to_string(path).failed_to(|| format!("read from `{}`", path.display()))
     }
 }
 
-fn restore_if_chain(path: &Path, marker: &Ident) -> Result<()> {
-    let contents = read_to_string(path).failed_to(|| format!("read from `{}`", path.display()))?;
-
-    let contents = find_and_replace(
-        &contents,
-        &[
-            format!(r"s/(?m)\bfn\s+{marker}\s*\(\)/if_chain!/g"),
-            format!(r"s/(?m)\|\s*{marker}\s*\|/if_chain!/g"),
-            format!(r"s/(?m)\s*\{{\s*{marker}\s*;\s*}}/;/g"),
-            format!(r"s/(?m)\bif\s+{marker}/then/g"),
-        ],
-    )?;
-
-    let mut file = OpenOptions::new()
-        .truncate(true)
-        .write(true)
-        .open(path)
-        .failed_to(|| format!("open `{}`", path.display()))?;
-    file.write_all(contents.as_bytes())
-        .failed_to(|| format!("write to `{}`", path.display()))?;
+/// Reads source from stdin and writes the formatted source to stdout. In check mode, a diff is
+/// written instead. If `emit` is [`Emit::Report`], a report is written instead. `stdin_filepath`
+/// is used to find `rustfmt`'s configuration. Returns `false` if in check mode and the source is
+/// not formatted.
+fn format_stdin(
+    stdin_filepath: Option<PathBuf>,
+    check: bool,
+    emit: Emit,
+    mut options: Options,
+) -> Result<bool> {
+    let mut original = String::new();
+    stdin()
+        .read_to_string(&mut original)
+        .failed_to(|| "read from stdin")?;
+
+    options.path = stdin_filepath;
+
+    let contents = format_str(&original, &options)?;
+
+    let path = options.path.as_deref().unwrap_or(Path::new("<stdin>"));
+
+    if let Emit::Report(format) = emit {
+        let checked = Checked {
+            path,
+            original: &original,
+            formatted: &contents,
+        };
+        print!("{}", report(format, &[checked]));
+        return Ok(!check || original == contents);
+    }
 
-    Ok(())
+    if check {
+        return Ok(!print_diff(path, &original, &contents));
+    }
+
+    print!("{contents}");
+
+    Ok(true)
 }
 
-fn rustfmt(args: &[String], path: Option<&Path>) -> Result<()> {
-    let mut command = Command::new("rustfmt");
-    command.args(args);
-    if let Some(path) = path {
-        command.arg(path);
+/// Prints a unified diff between `original` and `formatted`, if they differ. Returns `true` if a
+/// diff was printed.
+fn print_diff(path: &Path, original: &str, formatted: &str) -> bool {
+    if original == formatted {
+        return false;
     }
-    let status = command
-        .status()
-        .failed_to(|| format!("get status of {command:?}"))?;
 
-    ensure!(status.success(), "failed to format {:?}", path);
+    let path = path.to_string_lossy();
+    print!(
+        "{}",
+        TextDiff::from_lines(original, formatted)
+            .unified_diff()
+            .header(&path, &path)
+    );
 
-    Ok(())
+    true
 }
 
-fn match_if_chain(mac: &Macro) -> Option<(Span, &TokenStream)> {
-    if_chain! {
-        if let Macro {
-            path: path @ syn::Path { segments, .. },
-            bang_token,
-            delimiter: MacroDelimiter::Brace(_),
-            tokens,
-            ..
-        } = mac;
-        let segments = segments
-            .iter()
-            .map(|segment| segment.ident.to_string())
-            .collect::<Vec<_>>();
-        if let ["if_chain"] = segments
-            .iter()
-            .map(String::as_str)
-            .collect::<Vec<_>>()
-            .as_slice();
-        then {
-            Some((
-                path.span()
-                    .join(bang_token.span())
-                    .expect("`path` and `bang_token` should be from the same file"),
-                tokens,
-            ))
+#[allow(
+    clippy::case_sensitive_file_extension_comparisons,
+    clippy::too_many_lines
+)]
+fn process_args() -> Result<Args> {
+    let mut args = Vec::new();
+    let mut paths = Vec::new();
+    let mut changed_since = None;
+    let mut staged = false;
+    let mut index = false;
+    let mut install_hook = false;
+    let mut check = false;
+    let mut preformat_failure_is_warning = false;
+    let mut no_verify = false;
+    let mut convert_to_braces = false;
+    let mut migrate_to_let_chains = false;
+    let mut suggest_if_chain = false;
+    let mut fix = false;
+    let mut macro_names = Vec::new();
+    let mut excludes = Vec::new();
+    let mut emit = Emit::Files;
+    let mut files_with_diff = false;
+    let mut backup = false;
+    let mut verbose = false;
+    let mut stdin_filepath = None;
+    let mut jobs = None;
+    let mut iter = env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        if arg == "--help" || arg == "-h" {
+            usage();
+        } else if arg == "install-hook" {
+            install_hook = true;
+        } else if arg == "--changed-since" {
+            let value = iter
+                .next()
+                .ok_or_else(|| anyhow!("`--changed-since` requires a value"))?;
+            changed_since = Some(value);
+        } else if let Some(value) = arg.strip_prefix("--changed-since=") {
+            changed_since = Some(value.to_owned());
+        } else if arg == "--staged" {
+            staged = true;
+        } else if arg == "--index" {
+            index = true;
+        } else if arg == "--check" {
+            check = true;
+        } else if arg == "--preformat-failure-is-warning" {
+            preformat_failure_is_warning = true;
+        } else if arg == "--no-verify" {
+            no_verify = true;
+        } else if arg == "--convert-to-braces" {
+            convert_to_braces = true;
+        } else if arg == "--migrate-to-let-chains" {
+            migrate_to_let_chains = true;
+        } else if arg == "--suggest-if-chain" {
+            suggest_if_chain = true;
+        } else if arg == "--fix" {
+            fix = true;
+        } else if arg == "--macro-name" {
+            let value = iter
+                .next()
+                .ok_or_else(|| anyhow!("`--macro-name` requires a value"))?;
+            macro_names.push(value);
+        } else if let Some(value) = arg.strip_prefix("--macro-name=") {
+            macro_names.push(value.to_owned());
+        } else if arg == "--exclude" {
+            let value = iter
+                .next()
+                .ok_or_else(|| anyhow!("`--exclude` requires a value"))?;
+            excludes.push(value);
+        } else if let Some(value) = arg.strip_prefix("--exclude=") {
+            excludes.push(value.to_owned());
+        } else if arg == "--emit" {
+            let value = iter
+                .next()
+                .ok_or_else(|| anyhow!("`--emit` requires a value"))?;
+            parse_emit(&value, &mut emit, &mut args);
+        } else if let Some(value) = arg.strip_prefix("--emit=") {
+            parse_emit(value, &mut emit, &mut args);
+        } else if arg == "-l" || arg == "--files-with-diff" {
+            files_with_diff = true;
+        } else if arg == "--backup" {
+            backup = true;
+        } else if arg == "-v" || arg == "--verbose" {
+            verbose = true;
+        } else if arg == "--stdin-filepath" {
+            let value = iter
+                .next()
+                .ok_or_else(|| anyhow!("`--stdin-filepath` requires a value"))?;
+            stdin_filepath = Some(PathBuf::from(value));
+        } else if let Some(value) = arg.strip_prefix("--stdin-filepath=") {
+            stdin_filepath = Some(PathBuf::from(value));
+        } else if arg == "-j" || arg == "--jobs" {
+            let value = iter
+                .next()
+                .ok_or_else(|| anyhow!("`{arg}` requires a value"))?;
+            jobs = Some(parse_jobs(&value)?);
+        } else if let Some(value) = arg.strip_prefix("--jobs=") {
+            jobs = Some(parse_jobs(value)?);
+        } else if arg == "--config-path" {
+            // smoelius: The value may name a directory, which must not be mistaken for one to walk.
+            args.push(arg);
+            args.extend(iter.next());
+        } else if arg.to_lowercase().ends_with(".rs") || Path::new(&arg).is_dir() {
+            paths.push(arg);
         } else {
-            None
+            args.push(arg);
         }
     }
+    ensure!(
+        paths.is_empty() || (changed_since.is_none() && !staged && !index),
+        "`--changed-since`, `--staged`, and `--index` cannot be used with source file arguments"
+    );
+    ensure!(
+        !fix || suggest_if_chain,
+        "`--fix` requires `--suggest-if-chain`"
+    );
+    ensure!(
+        !(suggest_if_chain && migrate_to_let_chains),
+        "`--suggest-if-chain` and `--migrate-to-let-chains` cannot be used together"
+    );
+    // smoelius: Without `--fix`, the suggestions are shown as diffs, as with `--check`.
+    if suggest_if_chain && !fix {
+        check = true;
+    }
+    Ok(Args {
+        rustfmt_args: args,
+        paths,
+        changed_since,
+        staged,
+        index,
+        install_hook,
+        check,
+        preformat_failure_is_warning,
+        no_verify,
+        convert_to_braces,
+        migrate_to_let_chains,
+        suggest_if_chain,
+        macro_names,
+        excludes,
+        emit,
+        files_with_diff,
+        backup,
+        verbose,
+        stdin_filepath,
+        jobs: jobs.unwrap_or_else(|| available_parallelism().map_or(1, NonZeroUsize::get)),
+    })
+}
+
+/// Sets `emit` according to `value`. `rustfmt` would emit the rewritten files, so each of its modes
+/// is implemented here. Unknown values are forwarded to `rustfmt`, which reports them.
+fn parse_emit(value: &str, emit: &mut Emit, rustfmt_args: &mut Vec<String>) {
+    if value == "files" {
+        *emit = Emit::Files;
+    } else if value == "stdout" {
+        *emit = Emit::Stdout;
+    } else if let Some(format) = Format::parse(value) {
+        *emit = Emit::Report(format);
+    } else {
+        rustfmt_args.push(String::from("--emit"));
+        rustfmt_args.push(value.to_owned());
+    }
+}
+
+fn parse_jobs(value: &str) -> Result<usize> {
+    value
+        .parse::<NonZeroUsize>()
+        .map(NonZeroUsize::get)
+        .map_err(|_| anyhow!("`--jobs` requires a positive integer, found `{value}`"))
+}
+
+const USAGE: &str = "\
+Usage: rustfmt_if_chain [ARGS]
+
+`--backup`: Before modifying a source file, copy it to a file
+with the same name and the extension `.bk`.
+
+`--changed-since REV`: Instead of source file arguments, format
+the `.rs` files in the current directory that changed since REV,
+according to `git diff`.
+
+`--check`: Do not modify any source files. Instead, print a diff
+for each one that would change, and exit with 1 if any would.
+
+`--convert-to-braces`: Convert `if_chain!( ... )` and
+`if_chain![ ... ]` invocations to `if_chain! { ... }`. By
+default, each invocation keeps its delimiters.
+
+`--emit MODE`: With `stdout`, print the formatted source instead
+of modifying the source files. With `json`, `checkstyle`, or
+`sarif`, print a report of the lines that would change instead;
+with `--check`, exit with 1 if any would.
+
+`--exclude GLOB`: Do not format source files matching GLOB, a
+`.gitignore`-style pattern relative to the current directory.
+May be given more than once.
+
+`--index`: Like `--staged`, but format the files' staged
+contents and write the results to the git index. Each change is
+applied to the working tree too, if it applies cleanly. With
+`--check`, the staged contents are checked.
+
+`install-hook`: Instead of formatting, write a git pre-commit
+hook that runs `rustfmt_if_chain --check --index`. An existing
+hook is not overwritten.
+
+`-j N`, `--jobs N`: Format up to N files at once. Defaults to
+the number of CPUs. If started by `cargo` or `make`, their
+jobserver is used too.
+
+`-l`, `--files-with-diff`: Print the names of the source files
+that were modified, or with `--check`, that would be. With
+`--check`, no diffs are printed.
+
+`--macro-name NAME`: Treat invocations of NAME (e.g., `chain` or
+`my_crate::chain`) as `if_chain!` invocations. May be given more
+than once. Without this option, `use` items and `extern crate`
+items decide which names refer to `if_chain!`.
+
+`--migrate-to-let-chains`: Rewrite `if_chain!` invocations into
+`if` expressions that use let chains (edition 2024). Invocations
+that cannot be rewritten are reported. Unused `use` items for
+`if_chain!` are removed.
+
+`--no-verify`: Do not check that rewriting the `if_chain!`
+invocations for `rustfmt`, and restoring them afterward, changed
+nothing else. By default, a file that fails the check is not
+modified.
+
+`--preformat-failure-is-warning`: If `rustfmt` fails on an
+unmodified source file, print a warning instead of failing.
+
+`--staged`: Instead of source file arguments, format the `.rs`
+files in the current directory that are staged to be committed.
+With `--changed-since`, the staged files that changed since REV.
+
+`--stdin-filepath PATH`: Format source read from stdin as though
+it were read from PATH, e.g., use PATH to find `rustfmt.toml`.
+
+`--suggest-if-chain`: Print a diff that rewrites nested `if let`
+expressions without `else` arms into `if_chain!` invocations.
+With `--fix`, modify the source files instead. See \"How it
+works\".
+
+`-v`, `--verbose`: Print the name of each source file to stderr
+before formatting it.
+
+Arguments ending with `.rs` are considered source files and are
+formatted, as are the `.rs` files within directory arguments,
+except those ignored by `.gitignore`, `.ignore`, or
+`rustfmt.toml`'s `ignore`. If there are none, source is read
+from stdin and the formatted source is written to stdout. The
+options above are handled by `rustfmt_if_chain` itself. All
+other arguments are forwarded to `rustfmt`.\
+";
+
+fn usage() -> ! {
+    println!("{USAGE}");
+    exit(0);
 }
 
 #[test]
 fn usage_wrapping() {
     let re = regex::Regex::new(r"(?m)^.{65,}$").unwrap();
     let unwrapped =
-        find_and_replace(USAGE, [r"s/(?P<left>\S)\s(?P<right>\S)/$left $right/g"]).unwrap();
+        sedregex::find_and_replace(USAGE, [r"s/(?P<left>\S)\s(?P<right>\S)/$left $right/g"])
+            .unwrap();
     let mut prev = String::new();
     let mut rewrapped = unwrapped.to_string();
     while re.is_match(&rewrapped) && prev != rewrapped {
         prev = rewrapped;
-        rewrapped = find_and_replace(
+        rewrapped = sedregex::find_and_replace(
             &prev,
             [r"s/(?m)^(?P<line>.{0,64})\s/$line
 /g"],
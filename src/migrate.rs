@@ -0,0 +1,522 @@
+use crate::{
+    if_chain_body::{Clause, IfChainBody},
+    names::MacroNames,
+};
+use if_chain::if_chain;
+use proc_macro2::{LineColumn, Span, TokenStream, TokenTree};
+use quote::ToTokens;
+use rewriter::{OffsetCalculator, Rewriter};
+use std::{collections::HashSet, path::Path};
+use syn::{
+    BinOp, Expr, ExprBinary, ExprMacro, File, ItemMacro, ItemUse, Local, LocalInit, Macro, Pat,
+    PatIdent, PatType, StmtMacro, UseTree, parse_file,
+    spanned::Spanned,
+    visit::{Visit, visit_expr_macro, visit_item_macro, visit_pat_ident, visit_stmt_macro},
+};
+
+/// Rewrites the `if_chain!` invocations in `contents` into `if` expressions that use let chains.
+/// If no invocations remain afterwards, the `use` items that import `if_chain!` are removed too.
+/// Returns the new contents and descriptions of the invocations that could not be migrated.
+///
+/// If `contents` cannot be parsed, it is returned unchanged. The error is reported when the
+/// contents are formatted.
+pub fn migrate_to_let_chains(
+    path: &Path,
+    contents: &str,
+    names: &MacroNames,
+) -> (String, Vec<String>) {
+    let mut contents = contents.to_owned();
+    let mut migrated = false;
+
+    // smoelius: Only invocations that contain no other invocations are migrated. So nested
+    // invocations are migrated from the inside out, one level per pass.
+    loop {
+        let Ok(file) = parse_file(&contents) else {
+            return (contents, Vec::new());
+        };
+
+        let mut visitor = MigrateVisitor {
+            path,
+            contents: &contents,
+            lines: contents.lines().collect(),
+            offset_calculator: OffsetCalculator::new(&contents),
+            rewriter: Rewriter::new(&contents),
+            names: names.analyze(&file),
+            n_migrated: 0,
+            n_remaining: 0,
+            warnings: Vec::new(),
+        };
+
+        visitor.visit_file(&file);
+
+        if visitor.n_migrated == 0 {
+            let warnings = visitor.warnings;
+            if migrated && visitor.n_remaining == 0 {
+                contents = remove_imports(&contents, &file);
+            }
+            return (contents, warnings);
+        }
+
+        migrated = true;
+        contents = visitor.rewriter.contents();
+    }
+}
+
+struct MigrateVisitor<'migrate> {
+    path: &'migrate Path,
+    contents: &'migrate str,
+    lines: Vec<&'migrate str>,
+    offset_calculator: OffsetCalculator<'migrate>,
+    rewriter: Rewriter<'migrate, rewriter::Span>,
+    names: MacroNames,
+    n_migrated: usize,
+    n_remaining: usize,
+    warnings: Vec<String>,
+}
+
+/// Where the `if` clauses of an invocation's body begin and end
+struct Chain {
+    /// The index of the first `if` clause. The `let` statements before it are hoisted into a
+    /// block around the `if` expression.
+    first_if: usize,
+    /// The index of the last `if` clause. The `let` statements after it are moved into the `then`
+    /// block.
+    last_if: usize,
+}
+
+impl Visit<'_> for MigrateVisitor<'_> {
+    fn visit_item_macro(&mut self, item_macro: &ItemMacro) {
+        if self.names.matches(&item_macro.mac.path) {
+            self.not_migrated(item_macro.mac.path.span(), "it is an item");
+            return;
+        }
+        visit_item_macro(self, item_macro);
+    }
+
+    fn visit_stmt_macro(&mut self, stmt_macro: &StmtMacro) {
+        if self.migrate_macro(&stmt_macro.mac) {
+            return;
+        }
+        visit_stmt_macro(self, stmt_macro);
+    }
+
+    fn visit_expr_macro(&mut self, expr_macro: &ExprMacro) {
+        if self.migrate_macro(&expr_macro.mac) {
+            return;
+        }
+        visit_expr_macro(self, expr_macro);
+    }
+}
+
+impl MigrateVisitor<'_> {
+    /// Migrates `mac` if it is an `if_chain!` invocation. Returns true if it is one.
+    fn migrate_macro(&mut self, mac: &Macro) -> bool {
+        if !self.names.matches(&mac.path) {
+            return false;
+        }
+        let body = match mac.parse_body::<IfChainBody>() {
+            Ok(body) => body,
+            Err(error) => {
+                self.not_migrated(error.span(), &format!("its body is malformed: {error}"));
+                return true;
+            }
+        };
+        if self.contains_invocation(&body) {
+            let n_warnings = self.warnings.len();
+            self.visit_body(&body);
+            // smoelius: If a nested invocation cannot be migrated, then neither can this one.
+            if self.warnings.len() > n_warnings {
+                self.not_migrated(
+                    mac.path.span(),
+                    "it contains an invocation that was not migrated",
+                );
+            } else {
+                self.n_remaining += 1;
+            }
+            return true;
+        }
+        match check(&body) {
+            Ok(chain) => {
+                self.migrate(mac, &body, &chain);
+                self.n_migrated += 1;
+            }
+            Err((span, reason)) => self.not_migrated(span, &reason),
+        }
+        true
+    }
+
+    /// Rewrites `mac`, whose body is `body`, into an `if` expression. For example,
+    /// `if_chain! { let x = f(); if let Some(y) = x; if y > 0; let z = y; then { g(z) } }` becomes
+    /// `{ let x = f(); if let Some(y) = x && y > 0 { let z = y; g(z) } }`.
+    fn migrate(&mut self, mac: &Macro, body: &IfChainBody, chain: &Chain) {
+        let hoist = chain.first_if > 0;
+        let n_conditions = chain.last_if - chain.first_if + 1;
+        let delim_span = mac.delimiter.span();
+
+        // smoelius: The whitespace inside the delimiters is removed too, so that no blank lines are
+        // left behind.
+        self.rewrite_range(
+            mac.path.span().start(),
+            self.skip_whitespace_after(delim_span.open().end()),
+            if hoist { "{" } else { "" },
+        );
+
+        let mut trailing = Vec::new();
+        for (i, clause) in body.clauses.iter().enumerate() {
+            match clause {
+                Clause::If(if_token, expr, semi_token) => {
+                    if i != chain.first_if {
+                        self.rewrite(if_token.span, "&&");
+                    }
+                    if n_conditions > 1 && needs_parens(expr) {
+                        self.parenthesize(expr);
+                    }
+                    self.rewrite(semi_token.span, "");
+                }
+                Clause::Let(_) if i < chain.first_if => {}
+                Clause::Let(local) if i < chain.last_if => {
+                    self.rewrite(local.let_token.span, "&& let");
+                    if_chain! {
+                        if let Some(LocalInit { expr, .. }) = &local.init;
+                        if needs_parens(expr) || is_lazy_boolean(expr);
+                        then {
+                            self.parenthesize(expr);
+                        }
+                    }
+                    self.rewrite(local.semi_token.span, "");
+                }
+                Clause::Let(local) => {
+                    trailing.push(self.rewrite(local.span(), ""));
+                }
+            }
+        }
+
+        self.rewrite(body.then_token.span, "");
+
+        if !trailing.is_empty() {
+            self.rewrite(
+                body.then_branch.brace_token.span.open(),
+                &format!("{{\n{}", trailing.join("\n")),
+            );
+        }
+
+        self.rewrite_range(
+            self.trim_whitespace_before(delim_span.close().start()),
+            delim_span.close().end(),
+            if hoist { "}" } else { "" },
+        );
+    }
+
+    /// Returns true if any of `body`'s expressions or blocks contain an `if_chain!` invocation
+    fn contains_invocation(&self, body: &IfChainBody) -> bool {
+        let mut finder = Finder {
+            names: &self.names,
+            found: false,
+        };
+        visit_body(&mut finder, body);
+        finder.found
+    }
+
+    fn visit_body(&mut self, body: &IfChainBody) {
+        visit_body(self, body);
+    }
+
+    fn not_migrated(&mut self, span: Span, reason: &str) {
+        let start = span.start();
+        self.warnings.push(format!(
+            "{}:{}:{}: `if_chain!` invocation was not migrated because {reason}",
+            self.path.display(),
+            start.line,
+            start.column + 1
+        ));
+        self.n_remaining += 1;
+    }
+
+    fn parenthesize(&mut self, expr: &Expr) {
+        let (start, end) = self.offset_calculator.offsets_from_span(&expr.span());
+        let text = &self.contents[start..end];
+        self.rewrite(expr.span(), &format!("({text})"));
+    }
+
+    /// Returns the position of the first non-whitespace character at or after `position`
+    fn skip_whitespace_after(&self, mut position: LineColumn) -> LineColumn {
+        loop {
+            let line = self.lines[position.line - 1];
+            let suffix = line.chars().skip(position.column).collect::<String>();
+            let trimmed = suffix.trim_start();
+            if !trimmed.is_empty() || position.line == self.lines.len() {
+                return LineColumn {
+                    line: position.line,
+                    column: position.column + suffix.chars().count() - trimmed.chars().count(),
+                };
+            }
+            position = LineColumn {
+                line: position.line + 1,
+                column: 0,
+            };
+        }
+    }
+
+    /// Returns the position just after the last non-whitespace character before `position`
+    fn trim_whitespace_before(&self, mut position: LineColumn) -> LineColumn {
+        loop {
+            let line = self.lines[position.line - 1];
+            let prefix = line.chars().take(position.column).collect::<String>();
+            let trimmed = prefix.trim_end();
+            if !trimmed.is_empty() || position.line == 1 {
+                return LineColumn {
+                    line: position.line,
+                    column: trimmed.chars().count(),
+                };
+            }
+            position = LineColumn {
+                line: position.line - 1,
+                column: self.lines[position.line - 2].chars().count(),
+            };
+        }
+    }
+
+    fn rewrite(&mut self, span: Span, replacement: &str) -> String {
+        self.rewrite_range(span.start(), span.end(), replacement)
+    }
+
+    fn rewrite_range(&mut self, start: LineColumn, end: LineColumn, replacement: &str) -> String {
+        let span = rewriter::Span::new(line_column(start), line_column(end));
+        self.rewriter.rewrite(&span, replacement)
+    }
+}
+
+/// Checks that the invocation whose body is `body` can be migrated. On failure, returns a span
+/// and a reason.
+fn check(body: &IfChainBody) -> Result<Chain, (Span, String)> {
+    let is_if = |clause: &Clause| matches!(clause, Clause::If(..));
+    let (Some(first_if), Some(last_if)) = (
+        body.clauses.iter().position(is_if),
+        body.clauses.iter().rposition(is_if),
+    ) else {
+        return Err((body.then_token.span, String::from("it has no `if` clauses")));
+    };
+
+    let mut bindings = HashSet::new();
+    for clause in &body.clauses[first_if..last_if] {
+        match clause {
+            Clause::If(_, expr, _) => {
+                if let Expr::Let(expr_let) = expr {
+                    bindings.extend(pat_bindings(&expr_let.pat));
+                }
+            }
+            Clause::Let(local) => {
+                check_let(local)?;
+                bindings.extend(pat_bindings(&local.pat));
+            }
+        }
+    }
+
+    // smoelius: `if_chain!` places a copy of the `else` block at each level of nesting, where some
+    // of the chain's bindings are in scope. With let chains, none of them are.
+    if let Some((else_token, else_branch)) = &body.else_branch {
+        let mentioned = mentioned_idents(else_branch.to_token_stream());
+        if let Some(name) = bindings
+            .iter()
+            .filter(|name| mentioned.contains(*name))
+            .min()
+        {
+            return Err((
+                else_token.span,
+                format!("its `else` block refers to `{name}`, which is bound within the chain"),
+            ));
+        }
+    }
+
+    Ok(Chain { first_if, last_if })
+}
+
+/// Checks that `local`, a `let` statement between two `if` clauses, can be part of a let chain
+fn check_let(local: &Local) -> Result<(), (Span, String)> {
+    if let Pat::Type(PatType { colon_token, .. }) = &local.pat {
+        return Err((
+            colon_token.span,
+            String::from("a `let` statement within it has a type annotation"),
+        ));
+    }
+    match &local.init {
+        None => Err((
+            local.semi_token.span,
+            String::from("a `let` statement within it has no initializer"),
+        )),
+        Some(LocalInit {
+            diverge: Some((else_token, _)),
+            ..
+        }) => Err((
+            else_token.span,
+            String::from("a `let` statement within it has an `else` block"),
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
+/// Returns true if `expr` must be parenthesized to be an operand of `&&`
+fn needs_parens(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Binary(ExprBinary {
+            op: BinOp::Or(_),
+            ..
+        }) | Expr::Assign(_)
+            | Expr::Closure(_)
+            | Expr::Range(_)
+    )
+}
+
+/// Returns true if `expr` is `a && b` or `a || b`, which cannot be the scrutinee of a `let` in a
+/// let chain
+fn is_lazy_boolean(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Binary(ExprBinary {
+            op: BinOp::And(_) | BinOp::Or(_),
+            ..
+        })
+    )
+}
+
+/// Returns the identifiers bound by `pat`
+fn pat_bindings(pat: &Pat) -> HashSet<String> {
+    struct Bindings(HashSet<String>);
+
+    impl Visit<'_> for Bindings {
+        fn visit_pat_ident(&mut self, pat_ident: &PatIdent) {
+            self.0.insert(pat_ident.ident.to_string());
+            visit_pat_ident(self, pat_ident);
+        }
+    }
+
+    let mut bindings = Bindings(HashSet::new());
+    bindings.visit_pat(pat);
+    bindings.0
+}
+
+/// Returns the identifiers in `tokens`, including ones inside macro arguments and format strings
+fn mentioned_idents(tokens: TokenStream) -> HashSet<String> {
+    let mut idents = HashSet::new();
+    for token in tokens {
+        match token {
+            TokenTree::Group(group) => idents.extend(mentioned_idents(group.stream())),
+            TokenTree::Ident(ident) => {
+                idents.insert(ident.to_string());
+            }
+            // smoelius: Capture identifiers used in format strings, e.g., `{x}` or `{x:?}`.
+            TokenTree::Literal(literal) => {
+                let literal = literal.to_string();
+                idents.extend(literal.split('{').skip(1).filter_map(|argument| {
+                    let name = argument.split([':', '}']).next()?;
+                    Some(name.to_owned())
+                }));
+            }
+            TokenTree::Punct(_) => {}
+        }
+    }
+    idents
+}
+
+fn visit_body<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, body: &'ast IfChainBody) {
+    for clause in &body.clauses {
+        match clause {
+            Clause::If(_, expr, _) => visitor.visit_expr(expr),
+            Clause::Let(local) => visitor.visit_local(local),
+        }
+    }
+    visitor.visit_block(&body.then_branch);
+    if let Some((_, else_branch)) = &body.else_branch {
+        visitor.visit_block(else_branch);
+    }
+}
+
+struct Finder<'names> {
+    names: &'names MacroNames,
+    found: bool,
+}
+
+impl Visit<'_> for Finder<'_> {
+    fn visit_macro(&mut self, mac: &Macro) {
+        self.found |= self.names.matches(&mac.path);
+    }
+}
+
+/// Removes the `use` items in `file`, whose source is `contents`, that import only `if_chain!`.
+/// An item that occupies whole lines is removed along with its lines.
+fn remove_imports(contents: &str, file: &File) -> String {
+    struct Imports(Vec<Span>);
+
+    impl Visit<'_> for Imports {
+        fn visit_item_use(&mut self, item_use: &ItemUse) {
+            if imports_only_if_chain(&item_use.tree) {
+                self.0.push(item_use.span());
+            }
+        }
+    }
+
+    let mut imports = Imports(Vec::new());
+    imports.visit_file(file);
+
+    let lines = contents.split('\n').collect::<Vec<_>>();
+    let mut rewriter = Rewriter::<rewriter::Span>::new(contents);
+
+    for span in imports.0 {
+        let (mut start, mut end) = (line_column(span.start()), line_column(span.end()));
+        if_chain! {
+            let line_start = lines[start.line - 1];
+            let line_end = lines[end.line - 1];
+            if line_start
+                .chars()
+                .take(start.column)
+                .all(char::is_whitespace);
+            if line_end.chars().skip(end.column).all(char::is_whitespace);
+            if end.line < lines.len();
+            then {
+                start.column = 0;
+                end = rewriter::LineColumn {
+                    line: end.line + 1,
+                    column: 0,
+                };
+            }
+        }
+        rewriter.rewrite(&rewriter::Span::new(start, end), "");
+    }
+
+    rewriter.contents()
+}
+
+/// Returns true if `tree` imports `if_chain!` from the `if_chain` crate and nothing else
+fn imports_only_if_chain(tree: &UseTree) -> bool {
+    match tree {
+        UseTree::Path(use_path) if use_path.ident == "if_chain" => {
+            imports_only_if_chain_macro(&use_path.tree)
+        }
+        UseTree::Group(use_group) => {
+            !use_group.items.is_empty() && use_group.items.iter().all(imports_only_if_chain)
+        }
+        _ => false,
+    }
+}
+
+/// Returns true if `tree`, a tree within the `if_chain` crate, imports only `if_chain!`
+fn imports_only_if_chain_macro(tree: &UseTree) -> bool {
+    match tree {
+        UseTree::Name(use_name) => use_name.ident == "if_chain",
+        UseTree::Rename(use_rename) => use_rename.ident == "if_chain",
+        // smoelius: The `if_chain` crate exports nothing but the macro.
+        UseTree::Glob(_) => true,
+        UseTree::Group(use_group) => {
+            !use_group.items.is_empty() && use_group.items.iter().all(imports_only_if_chain_macro)
+        }
+        UseTree::Path(_) => false,
+    }
+}
+
+fn line_column(line_column: LineColumn) -> rewriter::LineColumn {
+    rewriter::LineColumn {
+        line: line_column.line,
+        column: line_column.column,
+    }
+}
@@ -0,0 +1,158 @@
+use crate::{failed_to::FailedTo, skip::is_skipped};
+use anyhow::{Result, bail};
+use if_chain::if_chain;
+use std::{
+    collections::HashSet,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+use syn::{Attribute, Expr, ExprLit, Item, ItemMod, Lit, Meta, parse_file};
+
+/// Returns `root` followed by the files of the out-of-line modules reachable from it
+///
+/// Modules are resolved the way `rustfmt` resolves them. In particular, `root` is treated as a
+/// crate root, i.e., its submodules are looked for in its own directory.
+///
+/// # Errors
+///
+/// If a file cannot be read, or if a module's file cannot be found.
+pub fn module_files(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    let mut worklist = vec![(root.to_path_buf(), Module::root(root))];
+
+    while let Some((path, module)) = worklist.pop() {
+        if !visited.insert(path.canonicalize().unwrap_or_else(|_| path.clone())) {
+            continue;
+        }
+
+        let contents =
+            read_to_string(&path).failed_to(|| format!("read from `{}`", path.display()))?;
+        // smoelius: A file that cannot be parsed is returned without its children. `rustfmt` then
+        // reports the error when the file is formatted.
+        let mut children = Vec::new();
+        if let Ok(file) = parse_file(&contents) {
+            module.collect_children(&file.items, &mut children)?;
+        }
+
+        files.push(path);
+
+        // smoelius: Push the children in reverse so that they are visited in declaration order.
+        worklist.extend(children.into_iter().rev());
+    }
+
+    Ok(files)
+}
+
+/// The directory in which a module's submodules are looked for
+#[derive(Clone)]
+struct Module {
+    dir: PathBuf,
+    /// The name of the file's module, if the file is not a `mod.rs` file, e.g., `foo` for `foo.rs`.
+    /// Submodules of such a module are looked for in `dir/foo`.
+    relative: Option<String>,
+}
+
+impl Module {
+    fn root(path: &Path) -> Self {
+        Self {
+            dir: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            relative: None,
+        }
+    }
+
+    fn collect_children(
+        &self,
+        items: &[Item],
+        children: &mut Vec<(PathBuf, Module)>,
+    ) -> Result<()> {
+        for item in items {
+            let Item::Mod(item_mod) = item else {
+                continue;
+            };
+            // smoelius: `rustfmt` does not format the files of skipped modules.
+            if is_skipped(&item_mod.attrs) {
+                continue;
+            }
+            if let Some((_, items)) = &item_mod.content {
+                self.inline(item_mod).collect_children(items, children)?;
+            } else {
+                children.push(self.out_of_line(item_mod)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn inline(&self, item_mod: &ItemMod) -> Self {
+        // smoelius: For an inline module, a `#[path]` attribute names a directory.
+        if let Some(path) = path_attr(&item_mod.attrs) {
+            return Self {
+                dir: self.dir.join(path),
+                relative: None,
+            };
+        }
+        let mut dir = self.dir.clone();
+        if let Some(relative) = &self.relative {
+            dir.push(relative);
+        }
+        dir.push(item_mod.ident.to_string());
+        Self {
+            dir,
+            relative: None,
+        }
+    }
+
+    fn out_of_line(&self, item_mod: &ItemMod) -> Result<(PathBuf, Module)> {
+        // smoelius: Files named by `#[path]` attributes are treated like `mod.rs` files.
+        if let Some(path) = path_attr(&item_mod.attrs) {
+            let path = self.dir.join(path);
+            let module = Self::root(&path);
+            return Ok((path, module));
+        }
+
+        let name = item_mod.ident.to_string();
+        let dir = self
+            .relative
+            .as_ref()
+            .map_or_else(|| self.dir.clone(), |relative| self.dir.join(relative));
+        let default_path = dir.join(format!("{name}.rs"));
+        let secondary_path = dir.join(&name).join("mod.rs");
+
+        match (default_path.exists(), secondary_path.exists()) {
+            (true, false) => Ok((
+                default_path,
+                Self {
+                    dir,
+                    relative: Some(name),
+                },
+            )),
+            (false, true) => {
+                let module = Self::root(&secondary_path);
+                Ok((secondary_path, module))
+            }
+            (false, false) => bail!(
+                "failed to resolve mod `{name}`: {} does not exist",
+                default_path.display()
+            ),
+            (true, true) => bail!(
+                "failed to resolve mod `{name}`: both {} and {} exist",
+                default_path.display(),
+                secondary_path.display()
+            ),
+        }
+    }
+}
+
+fn path_attr(attrs: &[Attribute]) -> Option<String> {
+    attrs.iter().find_map(|attr| {
+        if_chain! {
+            if let Meta::NameValue(meta) = &attr.meta;
+            if meta.path.is_ident("path");
+            if let Expr::Lit(ExprLit {
+                lit: Lit::Str(lit_str),
+                ..
+            }) = &meta.value;
+            then { Some(lit_str.value()) } else { None }
+        }
+    })
+}
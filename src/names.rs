@@ -0,0 +1,207 @@
+use if_chain::if_chain;
+use std::collections::HashSet;
+use syn::{
+    File, ItemExternCrate, ItemMacro, ItemUse, Path, UseTree,
+    visit::{Visit, visit_file},
+};
+
+/// The paths by which the `if_chain` crate's macro can be invoked in a file
+///
+/// By default, `if_chain!`, `if_chain::if_chain!`, and `::if_chain::if_chain!` refer to the macro.
+/// The `use` items and `extern crate` items of the file and of its crate root may add names (e.g.,
+/// `use if_chain::if_chain as chain;`) or shadow them (e.g., a local `macro_rules! if_chain`).
+/// Names given with `--macro-name` always refer to the macro.
+#[derive(Clone, Debug)]
+pub struct MacroNames {
+    /// Names that refer to the macro when used alone, e.g., `if_chain` or `chain`
+    names: HashSet<String>,
+    /// Names that refer to the `if_chain` crate, e.g., `if_chain`
+    crates: HashSet<String>,
+    /// Paths given with `--macro-name`, as lists of segments
+    overrides: Vec<Vec<String>>,
+    /// Names brought into scope by `use` items or `#[macro_use]`, in the order they were found
+    imported: Vec<String>,
+}
+
+impl MacroNames {
+    pub fn new(overrides: &[String]) -> Self {
+        Self {
+            names: HashSet::from([String::from("if_chain")]),
+            crates: HashSet::from([String::from("if_chain")]),
+            overrides: overrides
+                .iter()
+                .map(|name| {
+                    name.trim_start_matches("::")
+                        .split("::")
+                        .map(str::to_owned)
+                        .collect()
+                })
+                .collect(),
+            imported: Vec::new(),
+        }
+    }
+
+    /// Returns a copy of `self` updated to reflect the items in `file`, a crate root. Only the
+    /// items whose effects extend to the crate's other modules are considered, i.e., `extern crate`
+    /// items and `macro_rules!` definitions.
+    pub fn analyze_crate_root(&self, file: &File) -> Self {
+        self.analyze_with(file, true)
+    }
+
+    /// Returns a copy of `self` updated to reflect the items in `file`
+    pub fn analyze(&self, file: &File) -> Self {
+        self.analyze_with(file, false)
+    }
+
+    fn analyze_with(&self, file: &File, crate_root: bool) -> Self {
+        let mut visitor = Analyzer {
+            names: self.clone(),
+            shadowed: HashSet::new(),
+            crate_root,
+        };
+        visit_file(&mut visitor, file);
+        let Analyzer {
+            mut names,
+            shadowed,
+            ..
+        } = visitor;
+        names.names.retain(|name| !shadowed.contains(name));
+        names
+    }
+
+    /// Returns true if `path` refers to the `if_chain` crate's macro
+    pub fn matches(&self, path: &Path) -> bool {
+        let segments = path
+            .segments
+            .iter()
+            .map(|segment| segment.ident.to_string())
+            .collect::<Vec<_>>();
+        if self.overrides.contains(&segments) {
+            return true;
+        }
+        match segments.as_slice() {
+            [name] => self.names.contains(name),
+            [krate, name] => self.crates.contains(krate) && name == "if_chain",
+            _ => false,
+        }
+    }
+
+    /// Returns a path by which new invocations can refer to the macro without adding a `use` item,
+    /// if there is one. Paths given with `--macro-name` are preferred.
+    pub fn invocation_path(&self) -> Option<String> {
+        if let Some(segments) = self.overrides.first() {
+            return Some(segments.join("::"));
+        }
+        self.imported
+            .iter()
+            .find(|name| self.names.contains(*name))
+            .cloned()
+    }
+}
+
+struct Analyzer {
+    names: MacroNames,
+    /// Names brought into scope by something other than the `if_chain` crate
+    shadowed: HashSet<String>,
+    /// Whether `use` items should be ignored, as they are in a crate root's other modules
+    crate_root: bool,
+}
+
+impl Visit<'_> for Analyzer {
+    fn visit_item_extern_crate(&mut self, item_extern_crate: &ItemExternCrate) {
+        if item_extern_crate.ident != "if_chain" {
+            return;
+        }
+        if let Some((_, rename)) = &item_extern_crate.rename {
+            self.names.crates.insert(rename.to_string());
+        }
+        if item_extern_crate
+            .attrs
+            .iter()
+            .any(|attr| attr.path().is_ident("macro_use"))
+        {
+            self.insert(String::from("if_chain"));
+        }
+    }
+
+    fn visit_item_macro(&mut self, item_macro: &ItemMacro) {
+        // smoelius: A local `macro_rules!` shadows the name it defines.
+        if_chain! {
+            if item_macro.mac.path.is_ident("macro_rules");
+            if let Some(ident) = &item_macro.ident;
+            then {
+                self.shadowed.insert(ident.to_string());
+            }
+        }
+    }
+
+    fn visit_item_use(&mut self, item_use: &ItemUse) {
+        if !self.crate_root {
+            self.use_tree(&mut Vec::new(), &item_use.tree);
+        }
+    }
+}
+
+impl Analyzer {
+    fn use_tree(&mut self, prefix: &mut Vec<String>, tree: &UseTree) {
+        match tree {
+            UseTree::Path(use_path) => {
+                prefix.push(use_path.ident.to_string());
+                self.use_tree(prefix, &use_path.tree);
+                prefix.pop();
+            }
+            UseTree::Name(use_name) => {
+                self.import(
+                    prefix,
+                    &use_name.ident.to_string(),
+                    &use_name.ident.to_string(),
+                );
+            }
+            UseTree::Rename(use_rename) => {
+                self.import(
+                    prefix,
+                    &use_rename.ident.to_string(),
+                    &use_rename.rename.to_string(),
+                );
+            }
+            UseTree::Glob(_) => {
+                if self.is_if_chain_crate(prefix) {
+                    self.insert(String::from("if_chain"));
+                }
+            }
+            UseTree::Group(use_group) => {
+                for tree in &use_group.items {
+                    self.use_tree(prefix, tree);
+                }
+            }
+        }
+    }
+
+    /// Records that `name`, imported from `prefix`, is in scope as `rename`
+    fn import(&mut self, prefix: &[String], name: &str, rename: &str) {
+        // smoelius: `use if_chain;` and `use if_chain as ...;` import the crate itself.
+        if prefix.is_empty() && self.names.crates.contains(name) {
+            self.names.crates.insert(rename.to_owned());
+            return;
+        }
+        if_chain! {
+            if name == "if_chain";
+            if self.is_if_chain_crate(prefix);
+            then {
+                self.insert(rename.to_owned());
+            } else {
+                self.shadowed.insert(rename.to_owned());
+            }
+        }
+    }
+
+    /// Records that `name` refers to the macro because of an item in the file
+    fn insert(&mut self, name: String) {
+        self.names.names.insert(name.clone());
+        self.names.imported.push(name);
+    }
+
+    fn is_if_chain_crate(&self, prefix: &[String]) -> bool {
+        matches!(prefix, [krate] if self.names.crates.contains(krate))
+    }
+}
@@ -0,0 +1,60 @@
+use jobserver::Client;
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+/// Returns the jobserver `cargo` (or `make`) passed to this process, if any
+///
+/// This function should be called before any files are opened.
+pub fn jobserver() -> Option<Client> {
+    // smoelius: `from_env` is unsafe because the file descriptors named in the environment could
+    // have been closed and reused. Calling it before any files are opened avoids that.
+    unsafe { Client::from_env() }
+}
+
+/// Applies `f` to each of `items` using up to `jobs` threads, and returns the results in the order
+/// of `items`
+///
+/// If `client` is not `None`, each thread but the first acquires a token from it before applying
+/// `f`. The first thread uses the token implicitly held by this process.
+pub fn map<T, R, F>(jobs: usize, client: Option<&Client>, items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(items.iter().map(|_| None).collect::<Vec<_>>());
+
+    thread::scope(|scope| {
+        for thread_index in 0..jobs.min(items.len()) {
+            let (next, results, f) = (&next, &results, &f);
+            scope.spawn(move || {
+                loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(item) = items.get(index) else {
+                        break;
+                    };
+                    // smoelius: If a token cannot be acquired, proceed without one rather than
+                    // fail.
+                    let _token = client
+                        .filter(|_| thread_index != 0)
+                        .and_then(|client| client.acquire().ok());
+                    let result = f(item);
+                    results.lock().unwrap()[index] = Some(result);
+                }
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every item should have been processed"))
+        .collect()
+}
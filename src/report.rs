@@ -0,0 +1,223 @@
+use serde_json::{Value, json};
+use similar::TextDiff;
+use std::{fmt::Write, path::Path};
+
+/// A machine-readable report format, selected with `--emit`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Json,
+    Checkstyle,
+    Sarif,
+}
+
+impl Format {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "json" => Some(Self::Json),
+            "checkstyle" => Some(Self::Checkstyle),
+            "sarif" => Some(Self::Sarif),
+            _ => None,
+        }
+    }
+}
+
+/// A file checked for a report, with its original and formatted contents
+pub struct Checked<'a> {
+    pub path: &'a Path,
+    pub original: &'a str,
+    pub formatted: &'a str,
+}
+
+/// A range of lines that formatting would change. Line numbers are one-based and inclusive; an
+/// empty range's end is one less than its beginning.
+struct Mismatch {
+    original_begin_line: usize,
+    original_end_line: usize,
+    expected_begin_line: usize,
+    expected_end_line: usize,
+    /// The byte offset of the original range, and its length
+    original_offset: usize,
+    original_len: usize,
+    original: String,
+    expected: String,
+}
+
+/// Returns a report in `format` of the mismatches between the original and formatted contents of
+/// `files`. Files without mismatches are omitted, as they are by `rustfmt`.
+pub fn report(format: Format, files: &[Checked]) -> String {
+    let files = files
+        .iter()
+        .map(|file| (file.path, mismatches(file.original, file.formatted)))
+        .filter(|(_, mismatches)| !mismatches.is_empty())
+        .collect::<Vec<_>>();
+
+    match format {
+        Format::Json => json(&files),
+        Format::Checkstyle => checkstyle(&files),
+        Format::Sarif => sarif(&files),
+    }
+}
+
+fn mismatches(original: &str, formatted: &str) -> Vec<Mismatch> {
+    let diff = TextDiff::from_lines(original, formatted);
+    let old_lines = diff.old_slices();
+    let new_lines = diff.new_slices();
+
+    diff.grouped_ops(0)
+        .iter()
+        .filter_map(|group| {
+            let old_range = group.first()?.old_range().start..group.last()?.old_range().end;
+            let new_range = group.first()?.new_range().start..group.last()?.new_range().end;
+            let original_offset = old_lines[..old_range.start]
+                .iter()
+                .map(|line| line.len())
+                .sum();
+            let original = old_lines[old_range.clone()].concat();
+            Some(Mismatch {
+                original_begin_line: old_range.start + 1,
+                original_end_line: old_range.end,
+                expected_begin_line: new_range.start + 1,
+                expected_end_line: new_range.end,
+                original_offset,
+                original_len: original.len(),
+                original,
+                expected: new_lines[new_range].concat(),
+            })
+        })
+        .collect()
+}
+
+/// Produces the same format as `rustfmt --emit json`
+fn json(files: &[(&Path, Vec<Mismatch>)]) -> String {
+    let files = files
+        .iter()
+        .map(|(path, mismatches)| {
+            json!({
+                "name": path.to_string_lossy(),
+                "mismatches": mismatches
+                    .iter()
+                    .map(|mismatch| {
+                        json!({
+                            "original_begin_line": mismatch.original_begin_line,
+                            "original_end_line": mismatch.original_end_line,
+                            "expected_begin_line": mismatch.expected_begin_line,
+                            "expected_end_line": mismatch.expected_end_line,
+                            "original": mismatch.original,
+                            "expected": mismatch.expected,
+                        })
+                    })
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    format!("{}\n", Value::Array(files))
+}
+
+/// Produces the same format as `rustfmt --emit checkstyle`, i.e., one error per expected line
+fn checkstyle(files: &[(&Path, Vec<Mismatch>)]) -> String {
+    let mut report = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    report.push_str("<checkstyle version=\"4.3\">\n");
+    for (path, mismatches) in files {
+        let _ = writeln!(
+            report,
+            "<file name=\"{}\">",
+            escape_xml(&path.to_string_lossy())
+        );
+        for mismatch in mismatches {
+            for line in mismatch.expected.lines() {
+                let _ = writeln!(
+                    report,
+                    "<error line=\"{}\" severity=\"warning\" message=\"Should be `{}`\" />",
+                    mismatch.original_begin_line,
+                    escape_xml(line)
+                );
+            }
+        }
+        report.push_str("</file>\n");
+    }
+    report.push_str("</checkstyle>\n");
+    report
+}
+
+/// Produces a SARIF 2.1.0 log with one result per mismatch. Each result has a fix that replaces
+/// the original lines with the expected ones.
+fn sarif(files: &[(&Path, Vec<Mismatch>)]) -> String {
+    let results = files
+        .iter()
+        .flat_map(|(path, mismatches)| {
+            let uri = path.to_string_lossy().replace('\\', "/");
+            mismatches.iter().map(move |mismatch| {
+                json!({
+                    "ruleId": "formatting",
+                    "level": "warning",
+                    "message": {
+                        "text": format!("Should be:\n{}", mismatch.expected),
+                    },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": { "uri": uri },
+                            "region": {
+                                "startLine": mismatch.original_begin_line,
+                                "endLine": mismatch
+                                    .original_end_line
+                                    .max(mismatch.original_begin_line),
+                            },
+                        },
+                    }],
+                    "fixes": [{
+                        "description": { "text": "Format the source" },
+                        "artifactChanges": [{
+                            "artifactLocation": { "uri": uri },
+                            "replacements": [{
+                                "deletedRegion": {
+                                    "byteOffset": mismatch.original_offset,
+                                    "byteLength": mismatch.original_len,
+                                },
+                                "insertedContent": { "text": mismatch.expected },
+                            }],
+                        }],
+                    }],
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": [{
+                        "id": "formatting",
+                        "shortDescription": {
+                            "text": "Source is not formatted as `rustfmt_if_chain` would format it",
+                        },
+                    }],
+                },
+            },
+            "results": results,
+        }],
+    });
+
+    format!("{log:#}\n")
+}
+
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
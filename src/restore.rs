@@ -0,0 +1,242 @@
+use crate::{
+    failed_to::FailedTo,
+    rewrite::{Invocation, is_padded_marker},
+};
+use anyhow::{Result, anyhow, ensure};
+use if_chain::if_chain;
+use proc_macro2::{Delimiter, LineColumn, TokenTree};
+use quote::ToTokens;
+use rewriter::Rewriter;
+use std::{path::Path, slice::Iter};
+use syn::{
+    Block, Expr, ExprBlock, ExprCall, ExprClosure, ExprIf, ExprPath, Ident, ItemFn, Pat, PatIdent,
+    Stmt, parse_file,
+    spanned::Spanned,
+    visit::{Visit, visit_expr_closure, visit_expr_if, visit_item_fn},
+};
+
+/// Undoes the rewrites of `rewrite_if_chain` in `contents`, i.e., `rustfmt`'s output. The nodes
+/// the rewrites produced are found by parsing `contents`, and exactly their source ranges are
+/// rewritten.
+pub fn restore_if_chain(
+    path: &Path,
+    contents: &str,
+    marker: &Ident,
+    invocations: &[Invocation],
+) -> Result<String> {
+    let file = parse_file(contents)
+        .map_err(|error| anyhow!("{} at {:?}", error, error.span().start()))
+        .failed_to(|| format!("parse formatted `{}`", path.display()))?;
+
+    let mut visitor = RestoreVisitor {
+        rewriter: Rewriter::new(contents),
+        lines: contents.lines().collect(),
+        marker,
+        invocations: invocations.iter(),
+    };
+
+    visitor.visit_file(&file);
+
+    ensure!(
+        visitor.invocations.as_slice().is_empty(),
+        "failed to restore `{}`: {} `if_chain!` invocations were not found in `rustfmt`'s output",
+        path.display(),
+        visitor.invocations.len()
+    );
+
+    Ok(visitor.rewriter.contents())
+}
+
+struct RestoreVisitor<'restore> {
+    rewriter: Rewriter<'restore, rewriter::Span>,
+    lines: Vec<&'restore str>,
+    marker: &'restore Ident,
+    invocations: Iter<'restore, Invocation>,
+}
+
+impl Visit<'_> for RestoreVisitor<'_> {
+    /// `fn x()` -> `if_chain!`
+    fn visit_item_fn(&mut self, item_fn: &ItemFn) {
+        if is_padded_marker(&item_fn.sig.ident, self.marker) && item_fn.sig.inputs.is_empty() {
+            self.restore_invocation(
+                item_fn.sig.fn_token.span.start(),
+                item_fn.sig.paren_token.span.close().end(),
+                &item_fn.block,
+            );
+            return;
+        }
+        visit_item_fn(self, item_fn);
+    }
+
+    /// `|x|` -> `if_chain!`
+    fn visit_expr_closure(&mut self, expr_closure: &ExprClosure) {
+        if_chain! {
+            if let [Pat::Ident(PatIdent { ident, .. })] =
+                expr_closure.inputs.iter().collect::<Vec<_>>().as_slice();
+            if is_padded_marker(ident, self.marker);
+            if let Expr::Block(ExprBlock { block, .. }) = &*expr_closure.body;
+            then {
+                self.restore_invocation(
+                    expr_closure.or1_token.span.start(),
+                    expr_closure.or2_token.span.end(),
+                    block,
+                );
+                return;
+            }
+        }
+        visit_expr_closure(self, expr_closure);
+    }
+
+    /// `if x` -> `then`, `if ...? { x; }` -> `if ...;`, and `if ...(...)? { x(); }` -> `if ......;`
+    fn visit_expr_if(&mut self, expr_if: &ExprIf) {
+        if self.is_marker(&expr_if.cond) {
+            self.rewrite(
+                expr_if.if_token.span.start(),
+                expr_if.cond.span().end(),
+                "then",
+            );
+            self.visit_block(&expr_if.then_branch);
+            if let Some((_, else_branch)) = &expr_if.else_branch {
+                self.visit_expr(else_branch);
+            }
+            return;
+        }
+        if self.is_call_marker_block(&expr_if.then_branch) && expr_if.else_branch.is_none() {
+            let tokens = expr_if
+                .cond
+                .to_token_stream()
+                .into_iter()
+                .collect::<Vec<_>>();
+            if_chain! {
+                if let [.., TokenTree::Group(group), TokenTree::Punct(question)] =
+                    tokens.as_slice();
+                if group.delimiter() == Delimiter::Parenthesis;
+                if question.as_char() == '?';
+                then {
+                    let parens = group.delim_span();
+                    self.rewrite(parens.open().start(), parens.open().end(), "");
+                    self.visit_expr(&expr_if.cond);
+                    self.rewrite(parens.close().start(), question.span().end(), "");
+                } else {
+                    // smoelius: If the parentheses are missing, they are not removed, and
+                    // verification catches the difference.
+                    self.visit_expr(&expr_if.cond);
+                }
+            }
+            self.restore_semi(&expr_if.then_branch);
+            return;
+        }
+        if self.is_marker_block(&expr_if.then_branch) && expr_if.else_branch.is_none() {
+            self.visit_expr(&expr_if.cond);
+            if_chain! {
+                if let Some(TokenTree::Punct(question)) =
+                    expr_if.cond.to_token_stream().into_iter().last();
+                if question.as_char() == '?';
+                then {
+                    self.rewrite(question.span().start(), question.span().end(), "");
+                }
+            }
+            self.restore_semi(&expr_if.then_branch);
+            return;
+        }
+        visit_expr_if(self, expr_if);
+    }
+}
+
+impl RestoreVisitor<'_> {
+    /// Restores the path of the next invocation, which was rewritten to the text from `start` to
+    /// `end`, and the delimiters of its body, `block`
+    fn restore_invocation(&mut self, start: LineColumn, end: LineColumn, block: &Block) {
+        // smoelius: If the invocations run out, `x` is left in place for verification to catch.
+        let Some(invocation) = self.invocations.next() else {
+            self.visit_block(block);
+            return;
+        };
+        let braces = block.brace_token.span;
+        let (open, close) = match invocation.delimiter {
+            Delimiter::Parenthesis => ("(", ")"),
+            Delimiter::Bracket => ("[", "]"),
+            Delimiter::Brace | Delimiter::None => {
+                self.rewrite(start, end, &invocation.path);
+                self.visit_block(block);
+                return;
+            }
+        };
+        // smoelius: Unlike a brace, an opening parenthesis or bracket directly follows the `!`.
+        self.rewrite(
+            start,
+            braces.open().end(),
+            &format!("{}{open}", invocation.path),
+        );
+        self.visit_block(block);
+        self.rewrite(
+            braces.close().start(),
+            braces.close().end(),
+            &format!("{close}{}", if invocation.semi { ";" } else { "" }),
+        );
+    }
+
+    /// `{ x; }` or `{ x(); }` -> `;`
+    fn restore_semi(&mut self, block: &Block) {
+        // smoelius: Rewriting from the end of `cond` would drop any comments between `cond` and the
+        // block.
+        self.rewrite(
+            self.trim_whitespace_before(block.brace_token.span.open().start()),
+            block.brace_token.span.close().end(),
+            ";",
+        );
+    }
+
+    /// Returns true if `expr` is `x`
+    fn is_marker(&self, expr: &Expr) -> bool {
+        matches!(expr, Expr::Path(ExprPath { path, .. }) if path.is_ident(self.marker))
+    }
+
+    /// Returns true if `block` is `{ x; }`
+    fn is_marker_block(&self, block: &Block) -> bool {
+        matches!(
+            block.stmts.as_slice(),
+            [Stmt::Expr(expr, Some(_))] if self.is_marker(expr)
+        )
+    }
+
+    /// Returns true if `block` is `{ x(); }`
+    fn is_call_marker_block(&self, block: &Block) -> bool {
+        matches!(
+            block.stmts.as_slice(),
+            [Stmt::Expr(Expr::Call(ExprCall { func, args, .. }), Some(_))]
+                if args.is_empty() && self.is_marker(func)
+        )
+    }
+
+    /// Returns the position just after the last non-whitespace character before `position`
+    fn trim_whitespace_before(&self, mut position: LineColumn) -> LineColumn {
+        loop {
+            let line = self.lines[position.line - 1];
+            let prefix = line.chars().take(position.column).collect::<String>();
+            let trimmed = prefix.trim_end();
+            if !trimmed.is_empty() || position.line == 1 {
+                return LineColumn {
+                    line: position.line,
+                    column: trimmed.chars().count(),
+                };
+            }
+            position = LineColumn {
+                line: position.line - 1,
+                column: self.lines[position.line - 2].chars().count(),
+            };
+        }
+    }
+
+    fn rewrite(&mut self, start: LineColumn, end: LineColumn, replacement: &str) {
+        let span = rewriter::Span::new(line_column(start), line_column(end));
+        self.rewriter.rewrite(&span, replacement);
+    }
+}
+
+fn line_column(line_column: LineColumn) -> rewriter::LineColumn {
+    rewriter::LineColumn {
+        line: line_column.line,
+        column: line_column.column,
+    }
+}
@@ -0,0 +1,485 @@
+use crate::{
+    failed_to::FailedTo,
+    if_chain_body::{Clause, IfChainBody},
+    names::MacroNames,
+    skip::{
+        expr_attrs, impl_item_attrs, is_skipped, is_skipped_macro, item_attrs, skipped_macros,
+        trait_item_attrs,
+    },
+};
+use anyhow::{Result, anyhow};
+use if_chain::if_chain;
+use proc_macro2::{Delimiter, LineColumn, Span, TokenStream, TokenTree};
+use quote::{ToTokens, quote};
+use rewriter::Rewriter;
+use std::path::Path;
+use syn::{
+    Arm, Attribute, Expr, ExprBreak, ExprCast, ExprMacro, ExprRange, ExprReturn, ExprYield, File,
+    Ident, ImplItem, Item, ItemMacro, Local, Macro, MacroDelimiter, StmtMacro, Token, TraitItem,
+    parse_file,
+    spanned::Spanned,
+    visit::{
+        Visit, visit_arm, visit_expr, visit_expr_macro, visit_file, visit_impl_item, visit_item,
+        visit_item_macro, visit_local, visit_stmt_macro, visit_trait_item,
+    },
+};
+
+/// An `if_chain!` invocation rewritten by [`rewrite_if_chain`]
+#[derive(Clone)]
+pub struct Invocation {
+    /// The source text of the invocation's path and `!`, e.g., `if_chain!`
+    pub path: String,
+    /// The delimiter around the invocation's body. The rewrite always uses braces.
+    pub delimiter: Delimiter,
+    /// Whether the invocation is an item followed by `;`. The rewrite removes the `;`, because
+    /// `fn x() { ... };` is not a valid item.
+    pub semi: bool,
+    /// The first and last lines of the invocation in the source that was rewritten
+    pub first_line: usize,
+    pub last_line: usize,
+}
+
+impl Invocation {
+    /// Returns a copy of `self` whose body is delimited by braces
+    pub fn braced(&self) -> Self {
+        Self {
+            delimiter: Delimiter::Brace,
+            semi: false,
+            ..self.clone()
+        }
+    }
+}
+
+/// The result of [`rewrite_if_chain`]
+pub struct Rewritten {
+    pub contents: String,
+    /// The rewritten invocations, in the order in which they appear in the original contents
+    pub invocations: Vec<Invocation>,
+    /// Descriptions of the invocations that were left untouched because they are malformed
+    pub warnings: Vec<String>,
+}
+
+/// Rewrites the `if_chain!` invocations in `contents` into code `rustfmt` can format. `names` are
+/// the names by which the macro can be invoked in `contents`'s crate; they are refined using
+/// `contents`'s own items.
+///
+/// Invocations that `rustfmt` would leave alone are not rewritten, i.e., ones within nodes marked
+/// `#[rustfmt::skip]`, and ones of the macros listed in `skip_macro_invocations` or in
+/// `#[rustfmt::skip::macros(...)]`.
+pub fn rewrite_if_chain(
+    path: &Path,
+    contents: &str,
+    marker: &Ident,
+    names: &MacroNames,
+    skip_macro_invocations: &[String],
+) -> Result<Rewritten> {
+    let file = parse_file(contents)
+        .map_err(|error| anyhow!("{} at {:?}", error, error.span().start()))
+        .failed_to(|| format!("parse `{}`", path.display()))?;
+
+    let mut visitor = RewriteVisitor {
+        path,
+        rewriter: Rewriter::new(contents),
+        marker,
+        names: names.analyze(&file),
+        skipped_macros: skip_macro_invocations.to_vec(),
+        invocations: Vec::new(),
+        warnings: Vec::new(),
+    };
+
+    visitor.visit_file(&file);
+
+    Ok(Rewritten {
+        contents: visitor.rewriter.contents(),
+        invocations: visitor.invocations,
+        warnings: visitor.warnings,
+    })
+}
+
+/// Returns an identifier that does not appear in any of `contents`
+pub fn unused_ident(contents: &[&str]) -> Ident {
+    let mut i = 0;
+    loop {
+        let x = format!("x{i}");
+        if !contents.iter().any(|contents| contents.contains(&x)) {
+            return Ident::new(&x, Span::call_site());
+        }
+        i += 1;
+    }
+}
+
+/// The body of an `if_chain!` invocation, ready to be rewritten
+enum Body {
+    Parsed(IfChainBody),
+    /// The rewrites for a body that could not be parsed, as the starts and ends of the replaced
+    /// text
+    Tokens(Vec<(LineColumn, LineColumn, String)>),
+}
+
+struct RewriteVisitor<'rewrite> {
+    path: &'rewrite Path,
+    rewriter: Rewriter<'rewrite, rewriter::Span>,
+    marker: &'rewrite Ident,
+    names: MacroNames,
+    /// The macros whose invocations are left alone in the node being visited
+    skipped_macros: Vec<String>,
+    invocations: Vec<Invocation>,
+    warnings: Vec<String>,
+}
+
+impl Visit<'_> for RewriteVisitor<'_> {
+    fn visit_file(&mut self, file: &File) {
+        self.visit_attributed(&file.attrs, |visitor| visit_file(visitor, file));
+    }
+
+    fn visit_item(&mut self, item: &Item) {
+        self.visit_attributed(item_attrs(item), |visitor| visit_item(visitor, item));
+    }
+
+    fn visit_impl_item(&mut self, impl_item: &ImplItem) {
+        self.visit_attributed(impl_item_attrs(impl_item), |visitor| {
+            visit_impl_item(visitor, impl_item);
+        });
+    }
+
+    fn visit_trait_item(&mut self, trait_item: &TraitItem) {
+        self.visit_attributed(trait_item_attrs(trait_item), |visitor| {
+            visit_trait_item(visitor, trait_item);
+        });
+    }
+
+    fn visit_local(&mut self, local: &Local) {
+        self.visit_attributed(&local.attrs, |visitor| visit_local(visitor, local));
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        self.visit_attributed(expr_attrs(expr), |visitor| visit_expr(visitor, expr));
+    }
+
+    fn visit_arm(&mut self, arm: &Arm) {
+        self.visit_attributed(&arm.attrs, |visitor| visit_arm(visitor, arm));
+    }
+
+    fn visit_item_macro(&mut self, item_macro: &ItemMacro) {
+        if self.rewrite_macro(&item_macro.mac, true, item_macro.semi_token.as_ref()) {
+            return;
+        }
+        visit_item_macro(self, item_macro);
+    }
+
+    fn visit_stmt_macro(&mut self, stmt_macro: &StmtMacro) {
+        self.visit_attributed(&stmt_macro.attrs, |visitor| {
+            if !visitor.rewrite_macro(&stmt_macro.mac, true, None) {
+                visit_stmt_macro(visitor, stmt_macro);
+            }
+        });
+    }
+
+    fn visit_expr_macro(&mut self, expr_macro: &ExprMacro) {
+        if self.rewrite_macro(&expr_macro.mac, false, None) {
+            return;
+        }
+        visit_expr_macro(self, expr_macro);
+    }
+}
+
+impl RewriteVisitor<'_> {
+    /// Visits a node with `visit`, unless `attrs`, the node's attributes, mark it
+    /// `#[rustfmt::skip]`. The macros listed in `attrs`' `#[rustfmt::skip::macros(...)]` attributes
+    /// are skipped within the node.
+    fn visit_attributed(&mut self, attrs: &[Attribute], visit: impl FnOnce(&mut Self)) {
+        if is_skipped(attrs) {
+            return;
+        }
+        let n = self.skipped_macros.len();
+        self.skipped_macros.extend(skipped_macros(attrs));
+        visit(self);
+        self.skipped_macros.truncate(n);
+    }
+
+    /// Rewrites `mac` if it is an `if_chain!` invocation. `semi` is the `;` that follows `mac` if
+    /// `mac` is an item. An invocation whose body is malformed is left untouched, and a warning is
+    /// recorded.
+    fn rewrite_macro(&mut self, mac: &Macro, is_item: bool, semi: Option<&Token![;]>) -> bool {
+        let Some((span, tokens)) = match_if_chain(mac, &self.names) else {
+            return false;
+        };
+        if is_skipped_macro(&self.skipped_macros, &mac.path) {
+            return true;
+        }
+        let body = match self.parse_body(mac, tokens) {
+            Ok(body) => body,
+            Err(error) => {
+                let start = error.span().start();
+                self.warnings.push(format!(
+                    "{}:{}:{}: malformed `if_chain!` invocation was left unformatted: {error}",
+                    self.path.display(),
+                    start.line,
+                    start.column + 1
+                ));
+                return true;
+            }
+        };
+        let (delimiter, delim_span) = match &mac.delimiter {
+            MacroDelimiter::Paren(paren) => (Delimiter::Parenthesis, paren.span),
+            MacroDelimiter::Brace(brace) => (Delimiter::Brace, brace.span),
+            MacroDelimiter::Bracket(bracket) => (Delimiter::Bracket, bracket.span),
+        };
+        // smoelius: The marker is padded so that the rewritten text is as wide as the path, `!`,
+        // and opening delimiter it replaces, e.g., `|x0_____| {` for `if_chain! {`. Otherwise,
+        // `rustfmt` could break the surrounding lines where the final text would not fit.
+        let width = mac
+            .path
+            .to_token_stream()
+            .to_string()
+            .replace(' ', "")
+            .len()
+            + if delimiter == Delimiter::Brace { 3 } else { 2 };
+        let marker = if is_item {
+            padded_marker(self.marker, width.saturating_sub("fn () {".len()))
+        } else {
+            padded_marker(self.marker, width.saturating_sub("|| {".len()))
+        };
+        let path = self.rewrite(
+            span,
+            &if is_item {
+                quote! { fn #marker() }
+            } else {
+                quote! { |#marker| }
+            }
+            .to_string(),
+        );
+        self.invocations.push(Invocation {
+            path,
+            delimiter,
+            semi: semi.is_some(),
+            first_line: span.start().line,
+            last_line: semi.map_or(delim_span.close(), |semi| semi.span).end().line,
+        });
+        if delimiter != Delimiter::Brace {
+            self.rewrite(delim_span.open(), "{");
+        }
+        match body {
+            Body::Parsed(body) => self.rewrite_body(&body),
+            Body::Tokens(rewrites) => {
+                for (start, end, replacement) in rewrites {
+                    self.rewrite_range(start, end, &replacement);
+                }
+            }
+        }
+        if delimiter != Delimiter::Brace {
+            self.rewrite(delim_span.close(), "}");
+        }
+        if let Some(semi) = semi {
+            self.rewrite(semi.span, "");
+        }
+        true
+    }
+
+    /// Parses the body of `mac`, whose tokens are `tokens`
+    fn parse_body(&self, mac: &Macro, tokens: &TokenStream) -> syn::Result<Body> {
+        match mac.parse_body::<IfChainBody>() {
+            Ok(body) => Ok(Body::Parsed(body)),
+            // smoelius: If the body cannot be parsed, fall back to rewriting its tokens. Nested
+            // invocations are not formatted in that case. If the tokens cannot be rewritten either,
+            // the parse error describes what was expected.
+            Err(error) => self.token_rewrites(tokens).map(Body::Tokens).ok_or(error),
+        }
+    }
+
+    /// Rewrites the clauses of `body` and visits the expressions and blocks they contain, so that
+    /// nested invocations are rewritten too.
+    fn rewrite_body(&mut self, body: &IfChainBody) {
+        let marker = self.marker;
+        for clause in &body.clauses {
+            match clause {
+                Clause::If(_, expr, semi_token) => {
+                    let question_mark = question_mark(expr, &self.names);
+                    if let QuestionMark::Parenthesize(cast) = question_mark {
+                        self.insert(cast.span().start(), "(");
+                    }
+                    self.visit_expr(expr);
+                    // smoelius: The `?` is as wide as the `;` it stands in for, so `rustfmt` breaks
+                    // the condition where it would break the final text. It is inserted directly
+                    // after the condition, i.e., before any comments that precede the `;`. A
+                    // parenthesized cast is followed by `{ x(); }` rather than `{ x; }`, so that
+                    // the parentheses can be told apart from ones in the original.
+                    let block = match question_mark {
+                        QuestionMark::Append => {
+                            self.insert(expr.span().end(), "?");
+                            quote! { { #marker; } }
+                        }
+                        QuestionMark::Parenthesize(cast) => {
+                            self.insert(cast.span().end(), ")?");
+                            quote! { { #marker(); } }
+                        }
+                        QuestionMark::Omit => quote! { { #marker; } },
+                    };
+                    self.rewrite(semi_token.span, &block.to_string());
+                }
+                Clause::Let(local) => {
+                    self.visit_local(local);
+                }
+            }
+        }
+        self.rewrite(
+            body.then_token.span,
+            &quote! { if #marker }.to_token_stream().to_string(),
+        );
+        self.visit_block(&body.then_branch);
+        if let Some((_, else_branch)) = &body.else_branch {
+            self.visit_block(else_branch);
+        }
+    }
+
+    /// Returns the rewrites for a body that could not be parsed, found by looking for `;` followed
+    /// by `if`, `let`, or `then`. Returns `None` if the body has no `then`.
+    fn token_rewrites(
+        &self,
+        tokens: &TokenStream,
+    ) -> Option<Vec<(LineColumn, LineColumn, String)>> {
+        let marker = self.marker;
+        let mut rewrites = Vec::new();
+        let mut iter = tokens.clone().into_iter().peekable();
+        let mut curr_ends_let = if let Some(TokenTree::Ident(ident)) = iter.peek() {
+            ident == "let"
+        } else {
+            false
+        };
+        let mut prev = None;
+        while let Some(curr) = iter.next() {
+            match (&curr, iter.peek()) {
+                (TokenTree::Punct(punct), Some(TokenTree::Ident(next)))
+                    if punct.as_char() == ';'
+                        && ["if", "let", "then"].contains(&next.to_string().as_str()) =>
+                {
+                    if !curr_ends_let {
+                        // smoelius: Without a parsed condition, a `?` is added only where it
+                        // follows another. Restoration removes a `?` before every `{ x; }`.
+                        if_chain! {
+                            if let Some(TokenTree::Punct(prev)) = &prev;
+                            if prev.as_char() == '?';
+                            then {
+                                let end = prev.span().end();
+                                rewrites.push((end, end, String::from("?")));
+                            }
+                        }
+                        rewrites.push((
+                            curr.span().start(),
+                            curr.span().end(),
+                            quote! { { #marker; } }.to_token_stream().to_string(),
+                        ));
+                    }
+                    if *next == "then" {
+                        rewrites.push((
+                            next.span().start(),
+                            next.span().end(),
+                            quote! { if #marker }.to_token_stream().to_string(),
+                        ));
+                        return Some(rewrites);
+                    }
+                    curr_ends_let = *next == "let";
+                }
+                (_, _) => {}
+            }
+            prev = Some(curr);
+        }
+        None
+    }
+
+    fn rewrite(&mut self, span: Span, replacement: &str) -> String {
+        self.rewrite_range(span.start(), span.end(), replacement)
+    }
+
+    fn insert(&mut self, at: LineColumn, text: &str) {
+        self.rewrite_range(at, at, text);
+    }
+
+    fn rewrite_range(&mut self, start: LineColumn, end: LineColumn, replacement: &str) -> String {
+        let span = rewriter::Span::new(line_column(start), line_column(end));
+        self.rewriter.rewrite(&span, replacement)
+    }
+}
+
+fn line_column(line_column: LineColumn) -> rewriter::LineColumn {
+    rewriter::LineColumn {
+        line: line_column.line,
+        column: line_column.column,
+    }
+}
+
+/// Returns `marker` followed by enough underscores to make it `width` characters wide
+fn padded_marker(marker: &Ident, width: usize) -> Ident {
+    let marker = marker.to_string();
+    let padding = "_".repeat(width.saturating_sub(marker.len()));
+    Ident::new(&format!("{marker}{padding}"), Span::call_site())
+}
+
+/// Returns true if `ident` is `marker` padded by [`padded_marker`]
+pub fn is_padded_marker(ident: &Ident, marker: &Ident) -> bool {
+    ident
+        .to_string()
+        .strip_prefix(&marker.to_string())
+        .is_some_and(|padding| padding.chars().all(|c| c == '_'))
+}
+
+/// Where a `?` is added to the condition of an `if` clause
+enum QuestionMark<'expr> {
+    /// Directly after the condition
+    Append,
+    /// After the cast that ends the condition, once the cast is parenthesized
+    Parenthesize(&'expr ExprCast),
+    /// Nowhere
+    Omit,
+}
+
+/// Returns where a `?` can be added to `cond`, the condition of an `if` clause, so that it still
+/// parses. A `?` binds to the rightmost operand, which must not be a type (as in a cast) or missing
+/// (as in `a..`). A cast can take a `?` once it is parenthesized, though. Nor may the rightmost
+/// operand be an `if_chain!` invocation, whose rewritten body would absorb the `?`, i.e.,
+/// `|x| { ... }?`.
+fn question_mark<'expr>(cond: &'expr Expr, names: &MacroNames) -> QuestionMark<'expr> {
+    let recurse = |expr: &'expr Expr| question_mark(expr, names);
+    match cond {
+        Expr::Assign(expr) => recurse(&expr.right),
+        Expr::Binary(expr) => recurse(&expr.right),
+        Expr::Closure(expr) => recurse(&expr.body),
+        Expr::Let(expr) => recurse(&expr.expr),
+        Expr::RawAddr(expr) => recurse(&expr.expr),
+        Expr::Reference(expr) => recurse(&expr.expr),
+        Expr::Unary(expr) => recurse(&expr.expr),
+        Expr::Break(ExprBreak { expr, .. })
+        | Expr::Range(ExprRange { end: expr, .. })
+        | Expr::Return(ExprReturn { expr, .. })
+        | Expr::Yield(ExprYield { expr, .. }) => {
+            expr.as_deref().map_or(QuestionMark::Omit, recurse)
+        }
+        Expr::Macro(expr) if match_if_chain(&expr.mac, names).is_some() => QuestionMark::Omit,
+        Expr::Cast(expr) => QuestionMark::Parenthesize(expr),
+        Expr::Continue(_) | Expr::Verbatim(_) => QuestionMark::Omit,
+        _ => QuestionMark::Append,
+    }
+}
+
+fn match_if_chain<'mac>(mac: &'mac Macro, names: &MacroNames) -> Option<(Span, &'mac TokenStream)> {
+    if_chain! {
+        if let Macro {
+            path,
+            bang_token,
+            tokens,
+            ..
+        } = mac;
+        // smoelius: The path's text is restored exactly as written.
+        if names.matches(path);
+        then {
+            Some((
+                path.span()
+                    .join(bang_token.span())
+                    .expect("`path` and `bang_token` should be from the same file"),
+                tokens,
+            ))
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use crate::failed_to::FailedTo;
+use anyhow::{Result, ensure};
+use std::{
+    io::Write,
+    path::{Path, absolute},
+    process::{Command, Stdio},
+};
+
+/// Formats the file at `path`, a temporary copy of `original`. The copy's out-of-line modules are
+/// not formatted, since they are the original's modules.
+///
+/// `rustfmt`'s error messages are included in the returned error, with `path` replaced by
+/// `original`. Since several files may be formatted at once, this keeps each file's messages
+/// together.
+pub fn rustfmt(args: &[String], path: &Path, original: &Path) -> Result<()> {
+    let mut command = Command::new("rustfmt");
+    command.args(skip_children(args));
+    command.arg(path);
+    let output = command
+        .output()
+        .failed_to(|| format!("get output of {command:?}"))?;
+
+    let stderr = unmap_path(&String::from_utf8_lossy(&output.stderr), path, original);
+
+    ensure!(
+        output.status.success(),
+        "failed to format {:?}{}",
+        original,
+        messages(&stderr)
+    );
+
+    eprint!("{stderr}");
+
+    Ok(())
+}
+
+/// Formats the files at `paths` with one `rustfmt` invocation. Each path is paired with the file it
+/// is a temporary copy of. Returns, for each path, whether the file was formatted.
+///
+/// If `rustfmt` fails, a file is considered unformatted if `rustfmt`'s error messages mention it,
+/// or if they mention none of the files. No messages are printed in that case, since the caller is
+/// expected to format the unformatted files again, one at a time.
+pub fn rustfmt_batch(args: &[String], paths: &[(&Path, &Path)]) -> Vec<bool> {
+    if paths.is_empty() {
+        return Vec::new();
+    }
+
+    let mut command = Command::new("rustfmt");
+    command.args(skip_children(args));
+    command.args(paths.iter().map(|&(path, _)| path));
+    let Ok(output) = command.output() else {
+        return vec![false; paths.len()];
+    };
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    if output.status.success() {
+        let stderr = paths
+            .iter()
+            .fold(stderr.to_string(), |stderr, &(path, original)| {
+                unmap_path(&stderr, path, original)
+            });
+        eprint!("{stderr}");
+        return vec![true; paths.len()];
+    }
+
+    let mentioned = paths
+        .iter()
+        .map(|&(path, _)| {
+            path_variants(path)
+                .iter()
+                .any(|variant| stderr.contains(variant.as_str()))
+        })
+        .collect::<Vec<_>>();
+
+    if mentioned.contains(&true) {
+        mentioned.into_iter().map(|mentioned| !mentioned).collect()
+    } else {
+        vec![false; paths.len()]
+    }
+}
+
+/// Replaces mentions of `path`, a temporary copy of `original`, with `original` in `stderr`
+fn unmap_path(stderr: &str, path: &Path, original: &Path) -> String {
+    path_variants(path)
+        .iter()
+        .fold(stderr.to_owned(), |stderr, variant| {
+            stderr.replace(variant.as_str(), &original.to_string_lossy())
+        })
+}
+
+/// Returns the ways `rustfmt` may refer to `path`, i.e., by its absolute path or as given
+fn path_variants(path: &Path) -> Vec<String> {
+    path.canonicalize()
+        .into_iter()
+        .chain([path.to_path_buf()])
+        .map(|path| path.to_string_lossy().to_string())
+        .collect()
+}
+
+/// Returns `args` with `skip_children=true` added to their `--config` options
+///
+/// `rustfmt` honors only one `--config` option. So the values of all of them are merged into one.
+fn skip_children(args: &[String]) -> Vec<String> {
+    let mut configs = vec![String::from("skip_children=true")];
+    let mut other_args = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--config" {
+            configs.extend(iter.next().cloned());
+        } else if let Some(value) = arg.strip_prefix("--config=") {
+            configs.push(value.to_owned());
+        } else {
+            other_args.push(arg.clone());
+        }
+    }
+    other_args.push(format!("--config={}", configs.join(",")));
+    other_args
+}
+
+/// Formats `contents` by passing it to `rustfmt` on stdin. `rustfmt` is run in `path`'s directory
+/// so that it finds the same configuration it would if it were formatting `path` directly.
+pub fn rustfmt_stdin(args: &[String], path: &Path, contents: &str) -> Result<String> {
+    let mut command = Command::new("rustfmt");
+    command.args(absolute_config_path(args)?);
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        command.current_dir(parent);
+    }
+    command.stdin(Stdio::piped());
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::piped());
+    let mut child = command.spawn().failed_to(|| format!("spawn {command:?}"))?;
+    let mut stdin = child.stdin.take().unwrap();
+    stdin
+        .write_all(contents.as_bytes())
+        .failed_to(|| format!("write to {command:?}'s stdin"))?;
+    drop(stdin);
+    let output = child
+        .wait_with_output()
+        .failed_to(|| format!("wait on {command:?}"))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    ensure!(
+        output.status.success(),
+        "failed to format {:?}{}",
+        path,
+        messages(&stderr)
+    );
+
+    eprint!("{stderr}");
+
+    String::from_utf8(output.stdout).failed_to(|| format!("decode {command:?}'s output"))
+}
+
+/// Returns `args` with a relative `--config-path` made absolute. `rustfmt_stdin` runs `rustfmt` in
+/// another directory, but the path is relative to the current one (see [`config_file`]).
+///
+/// [`config_file`]: crate::config::config_file
+fn absolute_config_path(args: &[String]) -> Result<Vec<String>> {
+    let mut absolute_args = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--config-path" {
+            absolute_args.push(arg.clone());
+            if let Some(value) = iter.next() {
+                absolute_args.push(absolute_string(value)?);
+            }
+        } else if let Some(value) = arg.strip_prefix("--config-path=") {
+            absolute_args.push(format!("--config-path={}", absolute_string(value)?));
+        } else {
+            absolute_args.push(arg.clone());
+        }
+    }
+    Ok(absolute_args)
+}
+
+fn absolute_string(path: &str) -> Result<String> {
+    let absolute = absolute(path).failed_to(|| format!("make `{path}` absolute"))?;
+    Ok(absolute.to_string_lossy().to_string())
+}
+
+fn messages(stderr: &str) -> String {
+    if stderr.trim().is_empty() {
+        String::new()
+    } else {
+        format!(":\n{}", stderr.trim_end())
+    }
+}
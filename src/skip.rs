@@ -0,0 +1,140 @@
+use crate::config::{config_file, string_array};
+use if_chain::if_chain;
+use std::{fs::read_to_string, path::Path};
+use syn::{Attribute, Expr, ImplItem, Item, Meta, Token, TraitItem, punctuated::Punctuated};
+
+/// Returns true if `attrs` tell `rustfmt` to leave the node they are attached to alone, i.e., if
+/// they include `#[rustfmt::skip]`, `#[rustfmt_skip]`, or either within `#[cfg_attr(rustfmt, ...)]`
+pub fn is_skipped(attrs: &[Attribute]) -> bool {
+    rustfmt_metas(attrs).iter().any(|meta| {
+        let Meta::Path(path) = meta else {
+            return false;
+        };
+        path_string(path) == "rustfmt::skip" || path.is_ident("rustfmt_skip")
+    })
+}
+
+/// Returns the names of the macros listed in `attrs`' `#[rustfmt::skip::macros(...)]` attributes.
+/// `rustfmt` leaves the invocations of those macros alone within the node the attributes are
+/// attached to.
+pub fn skipped_macros(attrs: &[Attribute]) -> Vec<String> {
+    rustfmt_metas(attrs)
+        .iter()
+        .filter_map(|meta| match meta {
+            Meta::List(list) if path_string(&list.path) == "rustfmt::skip::macros" => list
+                .parse_args_with(Punctuated::<syn::Path, Token![,]>::parse_terminated)
+                .ok(),
+            _ => None,
+        })
+        .flatten()
+        .map(|path| path_string(&path))
+        .collect()
+}
+
+/// Returns true if `macros`, a list like that of `skip_macro_invocations`, includes `path`, the
+/// path of a macro invocation. `*` matches every invocation.
+pub fn is_skipped_macro(macros: &[String], path: &syn::Path) -> bool {
+    let path = path_string(path);
+    macros.iter().any(|name| name == "*" || *name == path)
+}
+
+/// Returns the attributes of `attrs` as `rustfmt` sees them, i.e., including those within
+/// `#[cfg_attr(rustfmt, ...)]`
+fn rustfmt_metas(attrs: &[Attribute]) -> Vec<Meta> {
+    let mut metas = Vec::new();
+    for attr in attrs {
+        if !attr.path().is_ident("cfg_attr") {
+            metas.push(attr.meta.clone());
+            continue;
+        }
+        if_chain! {
+            if let Ok(nested) =
+                attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated);
+            if let Some(Meta::Path(predicate)) = nested.first();
+            if predicate.is_ident("rustfmt");
+            then {
+                metas.extend(nested.into_iter().skip(1));
+            }
+        }
+    }
+    metas
+}
+
+fn path_string(path: &syn::Path) -> String {
+    path.segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+pub fn item_attrs(item: &Item) -> &[Attribute] {
+    match item {
+        Item::Const(item) => &item.attrs,
+        Item::Enum(item) => &item.attrs,
+        Item::ExternCrate(item) => &item.attrs,
+        Item::Fn(item) => &item.attrs,
+        Item::ForeignMod(item) => &item.attrs,
+        Item::Impl(item) => &item.attrs,
+        Item::Macro(item) => &item.attrs,
+        Item::Mod(item) => &item.attrs,
+        Item::Static(item) => &item.attrs,
+        Item::Struct(item) => &item.attrs,
+        Item::Trait(item) => &item.attrs,
+        Item::TraitAlias(item) => &item.attrs,
+        Item::Type(item) => &item.attrs,
+        Item::Union(item) => &item.attrs,
+        Item::Use(item) => &item.attrs,
+        _ => &[],
+    }
+}
+
+pub fn impl_item_attrs(impl_item: &ImplItem) -> &[Attribute] {
+    match impl_item {
+        ImplItem::Const(impl_item) => &impl_item.attrs,
+        ImplItem::Fn(impl_item) => &impl_item.attrs,
+        ImplItem::Type(impl_item) => &impl_item.attrs,
+        ImplItem::Macro(impl_item) => &impl_item.attrs,
+        _ => &[],
+    }
+}
+
+pub fn trait_item_attrs(trait_item: &TraitItem) -> &[Attribute] {
+    match trait_item {
+        TraitItem::Const(trait_item) => &trait_item.attrs,
+        TraitItem::Fn(trait_item) => &trait_item.attrs,
+        TraitItem::Type(trait_item) => &trait_item.attrs,
+        TraitItem::Macro(trait_item) => &trait_item.attrs,
+        _ => &[],
+    }
+}
+
+pub fn expr_attrs(expr: &Expr) -> &[Attribute] {
+    macro_rules! attrs {
+        ($($variant:ident),*) => {
+            match expr {
+                $(Expr::$variant(expr) => &expr.attrs,)*
+                _ => &[],
+            }
+        };
+    }
+    attrs!(
+        Array, Assign, Async, Await, Binary, Block, Break, Call, Cast, Closure, Const, Continue,
+        Field, ForLoop, Group, If, Index, Infer, Let, Lit, Loop, Macro, Match, MethodCall, Paren,
+        Path, Range, RawAddr, Reference, Repeat, Return, Struct, Try, TryBlock, Tuple, Unary,
+        Unsafe, While, Yield
+    )
+}
+
+/// Returns the value of `skip_macro_invocations` in the `rustfmt` configuration that applies to
+/// `path`. The configuration is the file named by `--config-path` in `rustfmt_args`, if any, or
+/// else the nearest `rustfmt.toml` or `.rustfmt.toml` in `path`'s directory or its ancestors.
+///
+/// The value is honored even where `rustfmt` does not honor it (it is unstable), since the
+/// invocations are formatted by `rustfmt_if_chain`.
+pub fn skip_macro_invocations(path: &Path, rustfmt_args: &[String]) -> Vec<String> {
+    config_file(path, rustfmt_args)
+        .and_then(|config| read_to_string(config).ok())
+        .map(|contents| string_array(&contents, "skip_macro_invocations"))
+        .unwrap_or_default()
+}
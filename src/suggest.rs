@@ -0,0 +1,242 @@
+use crate::names::MacroNames;
+use if_chain::if_chain;
+use proc_macro2::Span;
+use rewriter::{OffsetCalculator, Rewriter};
+use std::mem::{replace, take};
+use syn::{
+    BinOp, Expr, ExprBinary, ExprIf, File, Item, ItemMod, Stmt, parse_file,
+    spanned::Spanned,
+    visit::{Visit, visit_block, visit_expr, visit_item},
+};
+
+/// Rewrites each group of nested `if` expressions in `contents` that could be a single `if_chain!`
+/// invocation into one. For example, `if let Some(x) = f() { if x > 0 { g(x) } }` becomes
+/// `if_chain! { if let Some(x) = f(); if x > 0; then { g(x) } }`. If the file does not already
+/// import the macro, a `use` item is added. The same holds for each inline module, since `use`
+/// items do not extend into them.
+///
+/// If `contents` cannot be parsed, it is returned unchanged. The error is reported when the
+/// contents are formatted.
+pub fn suggest_if_chain(contents: &str, names: &MacroNames) -> String {
+    let Ok(file) = parse_file(contents) else {
+        return contents.to_owned();
+    };
+
+    let invocation_path = invocation_path(names, &file.items);
+
+    let mut visitor = SuggestVisitor::new(contents, names, invocation_path.as_deref());
+
+    visitor.visit_file(&file);
+
+    let n_converted = visitor.n_converted;
+
+    let mut converted = visitor.rewriter.contents();
+
+    // smoelius: Every rewrite is inside an item, so the text before the first item is unchanged.
+    if_chain! {
+        if n_converted > 0 && invocation_path.is_none();
+        if let Some(item) = file.items.first();
+        then {
+            let (offset, _) = OffsetCalculator::new(contents).offsets_from_span(&item.span());
+            converted.insert_str(offset, &format!("{}{}", USE, separator(item)));
+        }
+    }
+
+    converted
+}
+
+/// The `use` item added to modules that do not already import the macro
+const USE: &str = "use if_chain::if_chain;";
+
+struct SuggestVisitor<'original> {
+    contents: &'original str,
+    names: &'original MacroNames,
+    rewriter: Rewriter<'original>,
+    /// The path used in new invocations within the current module, e.g., `if_chain`
+    path: String,
+    /// The number of invocations added to the current module, not counting its inline modules
+    n_converted: usize,
+}
+
+impl Visit<'_> for SuggestVisitor<'_> {
+    fn visit_item_mod(&mut self, item_mod: &ItemMod) {
+        let Some((brace, items)) = &item_mod.content else {
+            return;
+        };
+
+        let invocation_path = invocation_path(self.names, items);
+
+        // smoelius: Whether the module needs a `use` item is known only after its invocations are
+        // added. But the item must be added before them, so the module is first visited with a
+        // separate rewriter whose results are discarded.
+        if_chain! {
+            if invocation_path.is_none();
+            if let Some(item) = items.first();
+            then {
+                let mut dry_run = SuggestVisitor::new(self.contents, self.names, None);
+                dry_run.visit_items(items);
+                if dry_run.n_converted > 0 {
+                    self.rewrite(
+                        brace.span.open(),
+                        &format!("{{\n{}{}", USE, separator(item)),
+                    );
+                }
+            }
+        }
+
+        let path = invocation_path.unwrap_or_else(|| String::from("if_chain"));
+        let outer_path = replace(&mut self.path, path);
+        let outer_n_converted = take(&mut self.n_converted);
+
+        self.visit_items(items);
+
+        self.path = outer_path;
+        self.n_converted = outer_n_converted;
+    }
+
+    fn visit_expr_if(&mut self, expr_if: &ExprIf) {
+        let chain = chain(expr_if);
+        if is_convertible(&chain) {
+            self.convert(&chain);
+        } else {
+            self.visit_branches(expr_if);
+        }
+    }
+}
+
+impl<'original> SuggestVisitor<'original> {
+    fn new(
+        contents: &'original str,
+        names: &'original MacroNames,
+        invocation_path: Option<&str>,
+    ) -> Self {
+        Self {
+            contents,
+            names,
+            rewriter: Rewriter::new(contents),
+            path: invocation_path.unwrap_or("if_chain").to_owned(),
+            n_converted: 0,
+        }
+    }
+
+    fn visit_items(&mut self, items: &[Item]) {
+        for item in items {
+            visit_item(self, item);
+        }
+    }
+
+    /// Rewrites `chain`, a list of nested `if` expressions, into an `if_chain!` invocation. Each
+    /// opening brace but the last becomes the `;` of an `if` clause. The closing braces of all but
+    /// the outermost and innermost expressions are removed.
+    fn convert(&mut self, chain: &[&ExprIf]) {
+        let (innermost, _) = chain.split_last().unwrap();
+
+        self.rewrite(chain[0].if_token.span, &format!("{}! {{ if", self.path));
+
+        for (i, expr_if) in chain.iter().enumerate() {
+            self.visit_expr(&expr_if.cond);
+            let replacement = if i + 1 < chain.len() { ";" } else { "; then {" };
+            self.rewrite(expr_if.then_branch.brace_token.span.open(), replacement);
+        }
+
+        // smoelius: The innermost block's contents may contain other nested `if` expressions.
+        visit_block(self, &innermost.then_branch);
+
+        for (i, expr_if) in chain.iter().enumerate().rev().skip(1) {
+            if let [Stmt::Expr(_, Some(semi))] = expr_if.then_branch.stmts.as_slice() {
+                self.rewrite(semi.span, "");
+            }
+            if i > 0 {
+                self.rewrite(expr_if.then_branch.brace_token.span.close(), "");
+            }
+        }
+
+        self.n_converted += 1;
+    }
+
+    /// Visits the condition and branches of `expr_if`. An `if` expression that follows `else` is
+    /// not converted, because `else` must be followed by a block or an `if`.
+    fn visit_branches(&mut self, expr_if: &ExprIf) {
+        visit_expr(self, &expr_if.cond);
+        visit_block(self, &expr_if.then_branch);
+        match expr_if.else_branch.as_ref().map(|(_, expr)| &**expr) {
+            Some(Expr::If(else_if)) => self.visit_branches(else_if),
+            Some(expr) => visit_expr(self, expr),
+            None => {}
+        }
+    }
+
+    fn rewrite(&mut self, span: Span, replacement: &str) -> String {
+        self.rewriter.rewrite(&span, replacement)
+    }
+}
+
+/// Returns a path by which new invocations within the module whose items are `items` can refer to
+/// the macro, if there is one. The items of inline modules are not considered, because `use`
+/// items within them do not affect the enclosing module.
+fn invocation_path(names: &MacroNames, items: &[Item]) -> Option<String> {
+    let file = File {
+        shebang: None,
+        attrs: Vec::new(),
+        items: items
+            .iter()
+            .filter(|item| !matches!(item, Item::Mod(item_mod) if item_mod.content.is_some()))
+            .cloned()
+            .collect(),
+    };
+    names.analyze(&file).invocation_path()
+}
+
+/// Returns the text that separates an added `use` item from `item`, the item that follows it
+fn separator(item: &Item) -> &'static str {
+    if matches!(item, Item::Use(_)) {
+        "\n"
+    } else {
+        "\n\n"
+    }
+}
+
+/// Returns `expr_if` followed by the `if` expressions nested within it. Each expression after the
+/// first is the only statement of the previous one's block. The last expression is the first
+/// whose block is not of that form.
+fn chain(expr_if: &ExprIf) -> Vec<&ExprIf> {
+    let mut chain = vec![expr_if];
+    if expr_if.else_branch.is_some() || !expr_if.attrs.is_empty() {
+        return chain;
+    }
+    let mut curr = expr_if;
+    while let [Stmt::Expr(Expr::If(next), _)] = curr.then_branch.stmts.as_slice() {
+        if next.else_branch.is_some() || !next.attrs.is_empty() {
+            break;
+        }
+        chain.push(next);
+        curr = next;
+    }
+    chain
+}
+
+/// Returns true if `chain` should be converted. At least one of the expressions must be an
+/// `if let`; nested `if` expressions without one are better combined with `&&`. Expressions whose
+/// conditions are let chains (e.g., `if let Some(x) = f() && x > 0`) are left alone.
+fn is_convertible(chain: &[&ExprIf]) -> bool {
+    chain.len() >= 2
+        && chain
+            .iter()
+            .any(|expr_if| matches!(*expr_if.cond, Expr::Let(_)))
+        && chain
+            .iter()
+            .all(|expr_if| matches!(*expr_if.cond, Expr::Let(_)) || !contains_let(&expr_if.cond))
+}
+
+fn contains_let(expr: &Expr) -> bool {
+    match expr {
+        Expr::Let(_) => true,
+        Expr::Binary(ExprBinary {
+            left,
+            op: BinOp::And(_),
+            right,
+            ..
+        }) => contains_let(left) || contains_let(right),
+        _ => false,
+    }
+}
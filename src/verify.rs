@@ -0,0 +1,260 @@
+use crate::{
+    failed_to::FailedTo,
+    rewrite::{Invocation, is_padded_marker},
+};
+use anyhow::{Result, anyhow, bail};
+use proc_macro2::{
+    Delimiter, Group, Ident, LineColumn, Punct, Spacing, Span, TokenStream, TokenTree,
+};
+use quote::ToTokens;
+use std::{iter::Zip, path::Path, slice::Iter, str::FromStr};
+use syn::parse_file;
+
+/// Checks that `rewritten`, with the rewrites of `rewrite_if_chain` undone at the token level, has
+/// the same tokens as `original`. Spans and whitespace are ignored.
+///
+/// Both the rewritten source and the source produced by `restore_if_chain` are checked this way.
+/// Together, the two checks ensure that rewriting and restoring change nothing but the rewritten
+/// tokens. The changes `rustfmt` makes in between are not checked.
+pub fn verify(
+    path: &Path,
+    original: &str,
+    rewritten: &str,
+    marker: &Ident,
+    invocations: &[Invocation],
+) -> Result<()> {
+    let paths = invocations
+        .iter()
+        .map(|invocation| {
+            TokenStream::from_str(&invocation.path)
+                .map_err(|error| anyhow!("{error}"))
+                .failed_to(|| format!("tokenize `{}`", invocation.path))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let original_tokens = tokens(path, original)?;
+    let rewritten_tokens = unrewrite(
+        tokens(path, rewritten)?,
+        marker,
+        &mut paths.iter().zip(invocations),
+    );
+
+    if let Err(span) = compare(original_tokens, rewritten_tokens) {
+        // smoelius: If `original` ends first, report the end of the file.
+        let start = span.map_or_else(
+            || LineColumn {
+                line: original.lines().count(),
+                column: 0,
+            },
+            |span| span.start(),
+        );
+        bail!(
+            "failed to verify `{}`: tokens differ at line {}, column {}",
+            path.display(),
+            start.line,
+            start.column + 1
+        );
+    }
+
+    Ok(())
+}
+
+fn tokens(path: &Path, contents: &str) -> Result<TokenStream> {
+    let file = parse_file(contents)
+        .map_err(|error| anyhow!("{} at {:?}", error, error.span().start()))
+        .failed_to(|| format!("parse `{}`", path.display()))?;
+    Ok(file.into_token_stream())
+}
+
+/// The invocations' paths, tokenized, paired with the invocations
+type Paths<'a> = Zip<Iter<'a, TokenStream>, Iter<'a, Invocation>>;
+
+/// Undoes the rewrites of `rewrite_if_chain`:
+/// - `fn x()` and `|x|` -> the invocation's path and `!`, taken from `paths`
+/// - `{ ... }` following `fn x()` or `|x|` -> the invocation's delimiters, and `;` if the
+///   invocation is an item followed by one
+/// - `{ x; }`, and `?` if it precedes it -> `;`
+/// - `(...)? { x(); }` -> `...;`
+/// - `if x` -> `then`
+fn unrewrite(tokens: TokenStream, marker: &Ident, paths: &mut Paths) -> TokenStream {
+    let tokens = tokens.into_iter().collect::<Vec<_>>();
+    let mut unrewritten = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i..] {
+            rest if is_rewritten_path(rest, marker) => {
+                // smoelius: If `paths` runs out, the rewritten tokens are left in place, which
+                // causes a mismatch.
+                if let Some((path, invocation)) = paths.next() {
+                    unrewritten.extend(path.clone());
+                    i += 3;
+                    if let Some(TokenTree::Group(group)) = tokens.get(i) {
+                        let mut new_group = Group::new(
+                            invocation.delimiter,
+                            unrewrite(group.stream(), marker, paths),
+                        );
+                        new_group.set_span(group.span());
+                        unrewritten.push(TokenTree::Group(new_group));
+                        i += 1;
+                    }
+                    if invocation.semi {
+                        unrewritten.push(TokenTree::Punct(Punct::new(';', Spacing::Alone)));
+                    }
+                } else {
+                    unrewritten.extend(rest[..3].iter().cloned());
+                    i += 3;
+                }
+            }
+            [TokenTree::Ident(if_), TokenTree::Ident(ident), ..]
+                if if_ == "if" && ident == marker =>
+            {
+                unrewritten.push(TokenTree::Ident(Ident::new("then", Span::call_site())));
+                i += 2;
+            }
+            [
+                TokenTree::Group(parens),
+                TokenTree::Punct(question),
+                TokenTree::Group(group),
+                ..,
+            ] if parens.delimiter() == Delimiter::Parenthesis
+                && question.as_char() == '?'
+                && is_call_marker_block(group, marker) =>
+            {
+                unrewritten.extend(unrewrite(parens.stream(), marker, paths));
+                unrewritten.push(TokenTree::Punct(Punct::new(';', Spacing::Alone)));
+                i += 3;
+            }
+            [TokenTree::Punct(question), TokenTree::Group(group), ..]
+                if question.as_char() == '?' && is_marker_block(group, marker) =>
+            {
+                unrewritten.push(TokenTree::Punct(Punct::new(';', Spacing::Alone)));
+                i += 2;
+            }
+            [TokenTree::Group(group), ..] if is_marker_block(group, marker) => {
+                unrewritten.push(TokenTree::Punct(Punct::new(';', Spacing::Alone)));
+                i += 1;
+            }
+            [TokenTree::Group(group), ..] => {
+                let mut new_group =
+                    Group::new(group.delimiter(), unrewrite(group.stream(), marker, paths));
+                new_group.set_span(group.span());
+                unrewritten.push(TokenTree::Group(new_group));
+                i += 1;
+            }
+            [token, ..] => {
+                unrewritten.push(token.clone());
+                i += 1;
+            }
+            [] => unreachable!(),
+        }
+    }
+    unrewritten.into_iter().collect()
+}
+
+/// Returns true if `tokens` begins with `fn x()` or `|x|`
+fn is_rewritten_path(tokens: &[TokenTree], marker: &Ident) -> bool {
+    match tokens {
+        [
+            TokenTree::Ident(fn_),
+            TokenTree::Ident(ident),
+            TokenTree::Group(group),
+            ..,
+        ] => {
+            fn_ == "fn"
+                && is_padded_marker(ident, marker)
+                && group.delimiter() == Delimiter::Parenthesis
+                && group.stream().is_empty()
+        }
+        [
+            TokenTree::Punct(left),
+            TokenTree::Ident(ident),
+            TokenTree::Punct(right),
+            ..,
+        ] => left.as_char() == '|' && is_padded_marker(ident, marker) && right.as_char() == '|',
+        _ => false,
+    }
+}
+
+/// Returns true if `group` is `{ x; }`
+fn is_marker_block(group: &Group, marker: &Ident) -> bool {
+    if group.delimiter() != Delimiter::Brace {
+        return false;
+    }
+    let tokens = group.stream().into_iter().collect::<Vec<_>>();
+    matches!(
+        tokens.as_slice(),
+        [TokenTree::Ident(ident), TokenTree::Punct(semi)]
+            if ident == marker && semi.as_char() == ';'
+    )
+}
+
+/// Returns true if `group` is `{ x(); }`
+fn is_call_marker_block(group: &Group, marker: &Ident) -> bool {
+    if group.delimiter() != Delimiter::Brace {
+        return false;
+    }
+    let tokens = group.stream().into_iter().collect::<Vec<_>>();
+    matches!(
+        tokens.as_slice(),
+        [TokenTree::Ident(ident), TokenTree::Group(args), TokenTree::Punct(semi)]
+            if ident == marker
+                && args.delimiter() == Delimiter::Parenthesis
+                && args.stream().is_empty()
+                && semi.as_char() == ';'
+    )
+}
+
+/// Compares `left` and `right` ignoring spans and spacing. On failure, returns the span of the
+/// first token in `left` that differs, or of the delimiter that closes `left` if `left` is shorter.
+fn compare(left: TokenStream, right: TokenStream) -> Result<(), Option<Span>> {
+    let mut left = left.into_iter();
+    let mut right = right.into_iter();
+    loop {
+        match (left.next(), right.next()) {
+            (None, None) => return Ok(()),
+            (None, Some(_)) => return Err(None),
+            (Some(TokenTree::Group(left)), Some(TokenTree::Group(right)))
+                if left.delimiter() == right.delimiter() =>
+            {
+                compare(left.stream(), right.stream())
+                    .map_err(|span| span.or(Some(left.span_close())))?;
+            }
+            (Some(TokenTree::Ident(left)), Some(TokenTree::Ident(right))) if left == right => {}
+            (Some(TokenTree::Punct(left)), Some(TokenTree::Punct(right)))
+                if left.as_char() == right.as_char() => {}
+            (Some(TokenTree::Literal(left)), Some(TokenTree::Literal(right)))
+                if left.to_string() == right.to_string() => {}
+            (Some(left), _) => return Err(Some(left.span())),
+        }
+    }
+}
+
+#[test]
+fn verify_detects_changed_tokens() {
+    let marker = Ident::new("x0", Span::call_site());
+    let original = "fn f() { if_chain! { if a; then { b(); } } }\n";
+    let rewritten = "fn f() { fn x0() { if a { x0; } if x0 { b(); } } }\n";
+    let changed = "fn f() { fn x0() { if a { x0; } if x0 { c(); } } }\n";
+
+    let invocations = [Invocation {
+        path: String::from("if_chain!"),
+        delimiter: Delimiter::Brace,
+        semi: false,
+        first_line: 1,
+        last_line: 1,
+    }];
+
+    verify(
+        Path::new("f.rs"),
+        original,
+        rewritten,
+        &marker,
+        &invocations,
+    )
+    .unwrap();
+
+    let error = verify(Path::new("f.rs"), original, changed, &marker, &invocations).unwrap_err();
+    assert_eq!(
+        "failed to verify `f.rs`: tokens differ at line 1, column 35",
+        error.to_string()
+    );
+}
@@ -0,0 +1,130 @@
+use crate::{
+    config::{config_file, string_array},
+    failed_to::FailedTo,
+};
+use anyhow::Result;
+use ignore::{
+    WalkBuilder,
+    gitignore::{Gitignore, GitignoreBuilder},
+};
+use std::{
+    collections::HashMap,
+    env::current_dir,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+/// Returns the `.rs` files in `dir` and its subdirectories, sorted by path. Hidden files and files
+/// excluded by `.gitignore` or `.ignore` files are skipped.
+///
+/// # Errors
+///
+/// If a directory or an ignore file cannot be read.
+pub fn rust_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    // smoelius: `.gitignore` files are honored even outside of a git repository, e.g., in an
+    // extracted package.
+    for entry in WalkBuilder::new(dir)
+        .require_git(false)
+        .sort_by_file_name(Ord::cmp)
+        .build()
+    {
+        let entry = entry.failed_to(|| format!("walk `{}`", dir.display()))?;
+        if entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_file())
+            && entry
+                .path()
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("rs"))
+        {
+            files.push(entry.into_path());
+        }
+    }
+
+    Ok(files)
+}
+
+/// Decides which source files to leave alone, namely, those matched by `--exclude` globs or by the
+/// `ignore` setting of the `rustfmt` configuration that applies to them
+pub struct Exclusions {
+    excludes: Gitignore,
+    rustfmt_args: Vec<String>,
+    /// The `ignore` patterns of each configuration file seen so far
+    ignores: HashMap<PathBuf, Gitignore>,
+}
+
+impl Exclusions {
+    /// Returns exclusions for `globs`, which are interpreted like `.gitignore` patterns in the
+    /// current directory. `rustfmt_args` are used to find `rustfmt`'s configuration (see
+    /// [`Options::rustfmt_args`](crate::Options::rustfmt_args)).
+    ///
+    /// # Errors
+    ///
+    /// If the current directory cannot be determined, or if a glob is invalid.
+    pub fn new(globs: &[String], rustfmt_args: &[String]) -> Result<Self> {
+        let dir = current_dir()
+            .and_then(|dir| dir.canonicalize())
+            .failed_to(|| "get current directory")?;
+        let excludes = gitignore(&dir, globs).failed_to(|| "parse `--exclude` globs")?;
+        Ok(Self {
+            excludes,
+            rustfmt_args: rustfmt_args.to_vec(),
+            ignores: HashMap::new(),
+        })
+    }
+
+    /// Returns true if `path` should be left alone
+    ///
+    /// # Errors
+    ///
+    /// If `path` cannot be canonicalized, or if the `ignore` setting that applies to it is invalid.
+    pub fn is_excluded(&mut self, path: &Path) -> Result<bool> {
+        let canonical = path
+            .canonicalize()
+            .failed_to(|| format!("canonicalize `{}`", path.display()))?;
+
+        if is_match(&self.excludes, &canonical) {
+            return Ok(true);
+        }
+
+        let Some(config) = config_file(path, &self.rustfmt_args) else {
+            return Ok(false);
+        };
+
+        if !self.ignores.contains_key(&config) {
+            let patterns = read_to_string(&config)
+                .map(|contents| string_array(&contents, "ignore"))
+                .unwrap_or_default();
+            let dir = config
+                .canonicalize()
+                .ok()
+                .and_then(|config| config.parent().map(Path::to_path_buf))
+                .unwrap_or_default();
+            let ignore = gitignore(&dir, &patterns)
+                .failed_to(|| format!("parse `ignore` setting of `{}`", config.display()))?;
+            self.ignores.insert(config.clone(), ignore);
+        }
+
+        Ok(is_match(&self.ignores[&config], &canonical))
+    }
+}
+
+/// Returns a matcher for `patterns`, which are interpreted like the lines of a `.gitignore` file in
+/// `dir`
+fn gitignore(dir: &Path, patterns: &[String]) -> Result<Gitignore, ignore::Error> {
+    let mut builder = GitignoreBuilder::new(dir);
+    for pattern in patterns {
+        builder.add_line(None, pattern)?;
+    }
+    builder.build()
+}
+
+fn is_match(gitignore: &Gitignore, canonical: &Path) -> bool {
+    // smoelius: `matched_path_or_any_parents` panics if the path is not within the root.
+    canonical.starts_with(gitignore.path())
+        && gitignore
+            .matched_path_or_any_parents(canonical, false)
+            .is_ignore()
+}
@@ -0,0 +1,83 @@
+use assert_cmd::Command;
+use std::{
+    fs::{create_dir_all, read_to_string, write},
+    path::Path,
+};
+use tempfile::tempdir;
+
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn workspace() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+
+    let tempdir = tempdir().unwrap();
+
+    write(
+        tempdir.path().join("Cargo.toml"),
+        "[workspace]\nmembers = [\"a\", \"b\"]\n",
+    )
+    .unwrap();
+
+    for (package, target) in [("a", "lib.rs"), ("b", "main.rs")] {
+        let package_dir = tempdir.path().join(package);
+        create_dir_all(package_dir.join("src")).unwrap();
+        write(
+            package_dir.join("Cargo.toml"),
+            format!("[package]\nname = \"{package}\"\nedition = \"2021\"\n"),
+        )
+        .unwrap();
+        write(
+            package_dir.join("src").join(target),
+            read_to_string(examples.join("before.rs")).unwrap(),
+        )
+        .unwrap();
+    }
+
+    Command::cargo_bin("cargo-if-chain-fmt")
+        .unwrap()
+        .args(["if-chain-fmt", "--check"])
+        .current_dir(&tempdir)
+        .assert()
+        .code(1);
+
+    Command::cargo_bin("cargo-if-chain-fmt")
+        .unwrap()
+        .args(["if-chain-fmt", "-p", "a"])
+        .current_dir(&tempdir)
+        .assert()
+        .success();
+
+    let after = read_to_string(examples.join("after.rs")).unwrap();
+    let before = read_to_string(examples.join("before.rs")).unwrap();
+
+    assert_eq!(
+        after,
+        read_to_string(tempdir.path().join("a/src/lib.rs")).unwrap()
+    );
+    assert_eq!(
+        before,
+        read_to_string(tempdir.path().join("b/src/main.rs")).unwrap()
+    );
+
+    Command::cargo_bin("cargo-if-chain-fmt")
+        .unwrap()
+        .args(["if-chain-fmt"])
+        .current_dir(tempdir.path().join("b"))
+        .assert()
+        .success();
+
+    assert_eq!(
+        after,
+        read_to_string(tempdir.path().join("b/src/main.rs")).unwrap()
+    );
+
+    Command::cargo_bin("cargo-if-chain-fmt")
+        .unwrap()
+        .args(["if-chain-fmt", "--all", "--check"])
+        .current_dir(&tempdir)
+        .assert()
+        .success();
+}
@@ -4,18 +4,30 @@
 extern crate test;
 
 use assert_cmd::Command;
-use std::{fs::copy, path::Path};
+use rustfmt_if_chain::{Options, WarningHandler, format_str};
+use std::{
+    fs::{copy, create_dir, read_to_string, write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 use tempfile::tempdir;
 
+const EXAMPLES: [(&str, &str); 7] = [
+    ("before.rs", "after.rs"),
+    ("comments_before.rs", "comments_after.rs"),
+    ("delimiters_before.rs", "delimiters_after.rs"),
+    ("let_before.rs", "let_after.rs"),
+    ("nested_before.rs", "nested_after.rs"),
+    ("path_before.rs", "path_after.rs"),
+    ("skip_before.rs", "skip_after.rs"),
+];
+
 #[cfg_attr(
     dylint_lib = "non_thread_safe_call_in_test",
     allow(non_thread_safe_call_in_test)
 )]
 #[test]
 fn example_test() {
-    const EXAMPLES: [(&str, &str); 2] =
-        [("before.rs", "after.rs"), ("let_before.rs", "let_after.rs")];
-
     let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
 
     for (before, after) in EXAMPLES {
@@ -38,6 +50,1372 @@ fn example_test() {
     }
 }
 
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn check_test() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+
+    for (before, after) in EXAMPLES {
+        let tempdir = tempdir().unwrap();
+
+        let before_path = tempdir.path().join(before);
+        let after_path = tempdir.path().join(after);
+
+        copy(examples.join(before), &before_path).unwrap();
+        copy(examples.join(after), &after_path).unwrap();
+
+        Command::cargo_bin("rustfmt_if_chain")
+            .unwrap()
+            .args(["--check", &before_path.to_string_lossy()])
+            .assert()
+            .code(1)
+            .stdout(predicates::str::contains("@@ -1,"));
+
+        assert_eq!(
+            read_to_string(examples.join(before)).unwrap(),
+            read_to_string(&before_path).unwrap()
+        );
+
+        Command::cargo_bin("rustfmt_if_chain")
+            .unwrap()
+            .args(["--check", &after_path.to_string_lossy()])
+            .assert()
+            .success()
+            .stdout("");
+    }
+}
+
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn stdin_test() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+
+    for (before, after) in EXAMPLES {
+        let tempdir = tempdir().unwrap();
+
+        Command::cargo_bin("rustfmt_if_chain")
+            .unwrap()
+            .args([
+                "--stdin-filepath",
+                &tempdir.path().join(before).to_string_lossy(),
+            ])
+            .pipe_stdin(examples.join(before))
+            .unwrap()
+            .assert()
+            .success()
+            .stdout(read_to_string(examples.join(after)).unwrap());
+    }
+
+    // smoelius: A relative `--config-path` is relative to the current directory, not to the
+    // directory of the `--stdin-filepath`.
+    let tempdir = tempdir().unwrap();
+
+    create_dir(tempdir.path().join("config")).unwrap();
+    create_dir(tempdir.path().join("src")).unwrap();
+    write(
+        tempdir.path().join("config/rustfmt.toml"),
+        "tab_spaces = 2\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .current_dir(&tempdir)
+        .args([
+            "--stdin-filepath",
+            "src/main.rs",
+            "--config-path",
+            "config/rustfmt.toml",
+        ])
+        .write_stdin(
+            "\
+fn main() {
+    if_chain! { if let Some(x) = f(); then { g(x) } }
+}
+",
+        )
+        .assert()
+        .success()
+        .stdout(
+            "\
+fn main() {
+  if_chain! {
+    if let Some(x) = f();
+    then {
+      g(x)
+    }
+  }
+}
+",
+        );
+}
+
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn module_test() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+
+    let tempdir = tempdir().unwrap();
+
+    let lib_path = tempdir.path().join("lib.rs");
+    let bar_path = tempdir.path().join("foo/bar.rs");
+    let baz_path = tempdir.path().join("other.rs");
+    let skipped_path = tempdir.path().join("skipped.rs");
+
+    write(
+        &lib_path,
+        "mod foo;\n#[path = \"other.rs\"]\nmod baz;\n\
+         #[cfg_attr(rustfmt, rustfmt::skip)]\nmod skipped;\n",
+    )
+    .unwrap();
+    write(tempdir.path().join("foo.rs"), "mod bar;\n").unwrap();
+    create_dir(tempdir.path().join("foo")).unwrap();
+    copy(examples.join("before.rs"), &bar_path).unwrap();
+    copy(examples.join("let_before.rs"), &baz_path).unwrap();
+    copy(examples.join("before.rs"), &skipped_path).unwrap();
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--check", &lib_path.to_string_lossy()])
+        .assert()
+        .code(1)
+        .stdout(predicates::str::contains(bar_path.to_string_lossy()))
+        .stdout(predicates::str::contains(baz_path.to_string_lossy()));
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .arg(&lib_path)
+        .assert()
+        .success();
+
+    Command::new("diff")
+        .args(&[bar_path, examples.join("after.rs")])
+        .assert()
+        .success();
+
+    Command::new("diff")
+        .args(&[baz_path, examples.join("let_after.rs")])
+        .assert()
+        .success();
+
+    Command::new("diff")
+        .args(&[skipped_path, examples.join("before.rs")])
+        .assert()
+        .success();
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--check", &lib_path.to_string_lossy()])
+        .assert()
+        .success()
+        .stdout("");
+}
+
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn directory_test() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+
+    let tempdir = tempdir().unwrap();
+
+    let src = tempdir.path().join("src");
+    create_dir(&src).unwrap();
+    create_dir(src.join("vendor")).unwrap();
+
+    write(src.join("lib.rs"), "mod foo;\nmod nested;\n").unwrap();
+    copy(examples.join("before.rs"), src.join("foo.rs")).unwrap();
+
+    // smoelius: `nested.rs`'s submodule is looked for in `src/nested`, because `nested.rs` is not a
+    // crate root.
+    create_dir(src.join("nested")).unwrap();
+    write(src.join("nested.rs"), "mod inner;\n").unwrap();
+    copy(examples.join("before.rs"), src.join("nested/inner.rs")).unwrap();
+    copy(examples.join("let_before.rs"), src.join("other.rs")).unwrap();
+
+    write(tempdir.path().join(".gitignore"), "generated.rs\n").unwrap();
+    write(tempdir.path().join(".ignore"), "vendor/\n").unwrap();
+    write(
+        tempdir.path().join("rustfmt.toml"),
+        "ignore = [\"src/ignored.rs\"]\n",
+    )
+    .unwrap();
+
+    let untouched = ["generated.rs", "vendor/bar.rs", "ignored.rs", "excluded.rs"];
+    for path in untouched {
+        copy(examples.join("before.rs"), src.join(path)).unwrap();
+    }
+
+    // smoelius: The modules of an excluded file are not resolved. A file whose modules cannot be
+    // resolved is skipped with a warning.
+    for path in ["excluded_mod.rs", "unresolved.rs"] {
+        write(src.join(path), "#[cfg(any())]\nmod missing;\n").unwrap();
+    }
+
+    let assert = Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .current_dir(&tempdir)
+        .args([
+            "--exclude",
+            "src/excluded.rs",
+            "--exclude",
+            "src/excluded_mod.rs",
+            ".",
+        ])
+        .assert()
+        .success()
+        .stderr(predicates::str::starts_with(format!(
+            "Warning: skipping `{}`: failed to resolve mod `missing`: {} does not exist\n",
+            Path::new(".").join("src/unresolved.rs").display(),
+            Path::new(".").join("src/missing.rs").display(),
+        )));
+
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert_eq!(1, stderr.matches("Warning: skipping").count());
+
+    for path in ["foo.rs", "nested/inner.rs"] {
+        Command::new("diff")
+            .args(&[src.join(path), examples.join("after.rs")])
+            .assert()
+            .success();
+    }
+
+    Command::new("diff")
+        .args(&[src.join("other.rs"), examples.join("let_after.rs")])
+        .assert()
+        .success();
+
+    for path in untouched {
+        Command::new("diff")
+            .args(&[src.join(path), examples.join("before.rs")])
+            .assert()
+            .success();
+    }
+}
+
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn git_test() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+
+    let tempdir = tempdir().unwrap();
+
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(&tempdir)
+            .assert()
+            .success();
+    };
+
+    git(&["init", "--quiet"]);
+
+    for path in ["committed.rs", "modified.rs"] {
+        copy(examples.join("before.rs"), tempdir.path().join(path)).unwrap();
+    }
+    git(&["add", "."]);
+    git(&["commit", "--quiet", "--message", "Initial commit"]);
+
+    // smoelius: With no changes, nothing is formatted, and source is not read from stdin.
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .current_dir(&tempdir)
+        .args(["--check", "-l", "--staged"])
+        .assert()
+        .success()
+        .stdout("");
+
+    copy(
+        examples.join("let_before.rs"),
+        tempdir.path().join("modified.rs"),
+    )
+    .unwrap();
+    copy(examples.join("before.rs"), tempdir.path().join("staged.rs")).unwrap();
+    git(&["add", "staged.rs"]);
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .current_dir(&tempdir)
+        .args(["--check", "-l", "--staged"])
+        .assert()
+        .code(1)
+        .stdout("staged.rs\n");
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .current_dir(&tempdir)
+        .args(["--changed-since", "HEAD"])
+        .assert()
+        .success();
+
+    for (path, expected) in [
+        ("committed.rs", "before.rs"),
+        ("modified.rs", "let_after.rs"),
+        ("staged.rs", "after.rs"),
+    ] {
+        Command::new("diff")
+            .args(&[tempdir.path().join(path), examples.join(expected)])
+            .assert()
+            .success();
+    }
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .current_dir(&tempdir)
+        .arg("install-hook")
+        .assert()
+        .success();
+
+    let hook = read_to_string(tempdir.path().join(".git/hooks/pre-commit")).unwrap();
+    assert!(hook.contains("rustfmt_if_chain --check --index"));
+
+    // smoelius: An existing hook is not overwritten.
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .current_dir(&tempdir)
+        .arg("install-hook")
+        .assert()
+        .failure();
+}
+
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn index_test() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+
+    let before = read_to_string(examples.join("before.rs")).unwrap();
+    let after = read_to_string(examples.join("after.rs")).unwrap();
+
+    let tempdir = tempdir().unwrap();
+
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .current_dir(&tempdir)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone()
+    };
+
+    git(&["init", "--quiet"]);
+
+    // smoelius: `clean.rs`'s unstaged edit does not overlap the formatting changes, but
+    // `conflicting.rs`'s does.
+    let clean = format!("// unstaged\n{before}");
+    let conflicting = before.replace("tree", "forest_tree");
+
+    for (path, unstaged) in [("clean.rs", &clean), ("conflicting.rs", &conflicting)] {
+        write(tempdir.path().join(path), &before).unwrap();
+        git(&["add", path]);
+        write(tempdir.path().join(path), unstaged).unwrap();
+    }
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .current_dir(&tempdir)
+        .args(["--check", "--index"])
+        .assert()
+        .code(1);
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .current_dir(&tempdir)
+        .args(["-l", "--index"])
+        .assert()
+        .success()
+        .stdout("clean.rs\nconflicting.rs\n")
+        .stderr(predicates::str::contains(
+            "`conflicting.rs` does not apply cleanly",
+        ));
+
+    for path in ["clean.rs", "conflicting.rs"] {
+        assert_eq!(after.as_bytes(), git(&["show", &format!(":{path}")]));
+    }
+
+    assert_eq!(
+        format!("// unstaged\n{after}"),
+        read_to_string(tempdir.path().join("clean.rs")).unwrap()
+    );
+    assert_eq!(
+        conflicting,
+        read_to_string(tempdir.path().join("conflicting.rs")).unwrap()
+    );
+
+    // smoelius: The working tree's unformatted contents do not affect the check.
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .current_dir(&tempdir)
+        .args(["--check", "--index"])
+        .assert()
+        .success()
+        .stdout("");
+
+    // smoelius: Within a subdirectory, the index entries of the files there are updated, not ones
+    // of the same names at the repository's root.
+    let sub = tempdir.path().join("sub");
+    create_dir(&sub).unwrap();
+    write(sub.join("nested.rs"), &before).unwrap();
+    git(&["add", "sub/nested.rs"]);
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .current_dir(&sub)
+        .arg("--index")
+        .assert()
+        .success();
+
+    assert_eq!(after.as_bytes(), git(&["show", ":sub/nested.rs"]));
+    assert_eq!(after, read_to_string(sub.join("nested.rs")).unwrap());
+    assert!(git(&["ls-files", "nested.rs"]).is_empty());
+}
+
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn braces_test() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+
+    let tempdir = tempdir().unwrap();
+
+    let path = tempdir.path().join("delimiters.rs");
+
+    copy(examples.join("delimiters_before.rs"), &path).unwrap();
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--convert-to-braces", &path.to_string_lossy()])
+        .assert()
+        .success();
+
+    assert_eq!(
+        read_to_string(examples.join("delimiters_braces_after.rs")).unwrap(),
+        read_to_string(&path).unwrap()
+    );
+}
+
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn malformed_test() {
+    const BEFORE: &str = "\
+fn main() {
+    if_chain! {
+        if let Some(x) = f();   if x > 0;
+        then { g(x) }
+    }
+    let y = if_chain!(if a; thn { 1 });
+}
+";
+    const AFTER: &str = "\
+fn main() {
+    if_chain! {
+        if let Some(x) = f();
+        if x > 0;
+        then {
+            g(x)
+        }
+    }
+    let y = if_chain!(if a; thn { 1 });
+}
+";
+
+    // smoelius: The malformed invocation is left untouched, and the rest of the file is formatted.
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--stdin-filepath", "malformed.rs"])
+        .write_stdin(BEFORE)
+        .assert()
+        .success()
+        .stdout(AFTER)
+        .stderr(
+            "Warning: malformed.rs:6:29: malformed `if_chain!` invocation was left unformatted: \
+             expected `if`, `let`, or `then`\n",
+        );
+}
+
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn migrate_test() {
+    const UNSAFE: &str = "\
+use if_chain::if_chain;
+
+fn main() {
+    let x = 0;
+    if_chain! {
+        if let Some(x) = f();
+        if x > 0;
+        then { g(x) } else { println!(\"{x}\") }
+    }
+    if_chain! { if a; then { b() } }
+}
+";
+    const UNSAFE_MIGRATED: &str = "\
+use if_chain::if_chain;
+
+fn main() {
+    let x = 0;
+    if_chain! {
+        if let Some(x) = f();
+        if x > 0;
+        then { g(x) } else { println!(\"{x}\") }
+    }
+    if a {
+        b()
+    }
+}
+";
+    const NESTED: &str = "\
+use if_chain::if_chain;
+
+fn main() {
+    if_chain! {
+        if a;
+        then {
+            let x = 0;
+            if_chain! {
+                if let Some(x) = f();
+                if x > 0;
+                then { g(x) } else { println!(\"{x}\") }
+            }
+        }
+    }
+}
+";
+
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+
+    let tempdir = tempdir().unwrap();
+
+    let path = tempdir.path().join("migrate.rs");
+
+    copy(examples.join("migrate_before.rs"), &path).unwrap();
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--migrate-to-let-chains", "--edition", "2024"])
+        .arg(&path)
+        .assert()
+        .success()
+        .stderr("");
+
+    assert_eq!(
+        read_to_string(examples.join("migrate_after.rs")).unwrap(),
+        read_to_string(&path).unwrap()
+    );
+
+    // smoelius: An invocation that cannot be migrated is reported, and because it remains, so does
+    // the `use` item.
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--migrate-to-let-chains", "--edition", "2024"])
+        .write_stdin(UNSAFE)
+        .assert()
+        .success()
+        .stdout(UNSAFE_MIGRATED)
+        .stderr(
+            "Warning: <stdin>:8:23: `if_chain!` invocation was not migrated because its `else` \
+             block refers to `x`, which is bound within the chain\n",
+        );
+
+    // smoelius: An invocation that contains one that cannot be migrated is reported too.
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--migrate-to-let-chains", "--edition", "2024"])
+        .write_stdin(NESTED)
+        .assert()
+        .success()
+        .stdout(NESTED)
+        .stderr(
+            "Warning: <stdin>:11:31: `if_chain!` invocation was not migrated because its `else` \
+             block refers to `x`, which is bound within the chain\n\
+             Warning: <stdin>:4:5: `if_chain!` invocation was not migrated because it contains an \
+             invocation that was not migrated\n",
+        );
+
+    // smoelius: Let chains require edition 2024.
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .arg("--migrate-to-let-chains")
+        .write_stdin(UNSAFE)
+        .assert()
+        .failure()
+        .stderr(predicates::str::starts_with(
+            "Error: failed to migrate `<stdin>` to let chains: let chains require edition 2024, \
+             but `rustfmt` would parse it as edition 2015",
+        ));
+}
+
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn names_test() {
+    const UNFORMATTED: &str = "\
+fn main() {
+    chain! {
+        if let Some(x) = f();   if x > 0;
+        then { g(x) }
+    }
+}
+";
+    const FORMATTED: &str = "\
+fn main() {
+    chain! {
+        if let Some(x) = f();
+        if x > 0;
+        then {
+            g(x)
+        }
+    }
+}
+";
+    const SHADOWED: &str = "\
+macro_rules! if_chain {
+    ($($tt:tt)*) => {};
+}
+
+fn main() {
+    if_chain! { not an if_chain invocation }
+}
+";
+
+    let alias = "use if_chain::if_chain as chain;\n\n";
+
+    // smoelius: An alias is recognized only if it is imported or named with `--macro-name`.
+    for (args, before, after) in [
+        (
+            &[][..],
+            format!("{alias}{UNFORMATTED}"),
+            format!("{alias}{FORMATTED}"),
+        ),
+        (&[][..], UNFORMATTED.to_owned(), UNFORMATTED.to_owned()),
+        (
+            &["--macro-name", "chain"][..],
+            UNFORMATTED.to_owned(),
+            FORMATTED.to_owned(),
+        ),
+        (&[][..], SHADOWED.to_owned(), SHADOWED.to_owned()),
+    ] {
+        Command::cargo_bin("rustfmt_if_chain")
+            .unwrap()
+            .args(args)
+            .write_stdin(before)
+            .assert()
+            .success()
+            .stdout(after);
+    }
+
+    // smoelius: A `macro_rules! if_chain` in the crate root shadows `if_chain` in the crate's other
+    // modules.
+    let tempdir = tempdir().unwrap();
+
+    let lib_path = tempdir.path().join("lib.rs");
+    let foo_path = tempdir.path().join("foo.rs");
+
+    write(
+        &lib_path,
+        "macro_rules! if_chain {\n    ($($tt:tt)*) => {};\n}\n\nmod foo;\n",
+    )
+    .unwrap();
+    write(
+        &foo_path,
+        "fn main() {\n    if_chain! { not an if_chain invocation }\n}\n",
+    )
+    .unwrap();
+
+    for args in [&["--check"][..], &[][..]] {
+        Command::cargo_bin("rustfmt_if_chain")
+            .unwrap()
+            .args(args)
+            .arg(&lib_path)
+            .assert()
+            .success()
+            .stdout("");
+    }
+}
+
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn report_test() {
+    const UNFORMATTED: &str = "\
+fn main() {
+    if_chain! { if let Some(x) = f(); then { g(x) } }
+}
+";
+    const JSON: &str = concat!(
+        r#"[{"mismatches":[{"#,
+        r#""expected":"    if_chain! {\n        if let Some(x) = f();\n        then {\n"#,
+        r#"            g(x)\n        }\n    }\n","#,
+        r#""expected_begin_line":2,"expected_end_line":7,"#,
+        r#""original":"    if_chain! { if let Some(x) = f(); then { g(x) } }\n","#,
+        r#""original_begin_line":2,"original_end_line":2}],"name":"<stdin>"}]"#,
+        "\n"
+    );
+
+    // smoelius: The report describes the original source, not the rewritten one. Without `--check`,
+    // mismatches do not cause failure.
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--emit", "json"])
+        .write_stdin(UNFORMATTED)
+        .assert()
+        .success()
+        .stdout(JSON);
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--check", "--emit=checkstyle"])
+        .write_stdin(UNFORMATTED)
+        .assert()
+        .code(1)
+        .stdout(predicates::str::contains(
+            "<error line=\"2\" severity=\"warning\" message=\"Should be `        if let Some(x) = \
+             f();`\" />",
+        ));
+
+    let tempdir = tempdir().unwrap();
+
+    let path = tempdir.path().join("main.rs");
+
+    write(&path, UNFORMATTED).unwrap();
+
+    let assert = Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--emit", "sarif"])
+        .arg(&path)
+        .assert()
+        .success();
+
+    assert_eq!(UNFORMATTED, read_to_string(&path).unwrap());
+
+    let sarif = serde_json::from_slice::<serde_json::Value>(&assert.get_output().stdout).unwrap();
+    let result = &sarif["runs"][0]["results"][0];
+    assert_eq!(
+        2,
+        result["locations"][0]["physicalLocation"]["region"]["startLine"]
+    );
+    let replacement = &result["fixes"][0]["artifactChanges"][0]["replacements"][0];
+    assert_eq!(12, replacement["deletedRegion"]["byteOffset"]);
+    assert_eq!(
+        concat!(
+            "    if_chain! {\n",
+            "        if let Some(x) = f();\n",
+            "        then {\n",
+            "            g(x)\n",
+            "        }\n",
+            "    }\n",
+        ),
+        replacement["insertedContent"]["text"]
+    );
+}
+
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn rustfmt_options_test() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+
+    let tempdir = tempdir().unwrap();
+
+    let before_path = tempdir.path().join("before.rs");
+    let after_path = tempdir.path().join("after.rs");
+
+    copy(examples.join("before.rs"), &before_path).unwrap();
+    copy(examples.join("after.rs"), &after_path).unwrap();
+
+    let before = read_to_string(&before_path).unwrap();
+    let after = read_to_string(&after_path).unwrap();
+
+    // smoelius: Each option must describe the source files, not the rewritten files `rustfmt`
+    // sees.
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--emit", "stdout"])
+        .args([&before_path, &after_path])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}:\n\n{after}{}:\n\n{after}",
+            before_path.display(),
+            after_path.display()
+        ));
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--emit", "stdout", "-q"])
+        .args([&before_path, &after_path])
+        .assert()
+        .success()
+        .stdout(format!("{after}{after}"));
+
+    assert_eq!(before, read_to_string(&before_path).unwrap());
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--check", "-l"])
+        .args([&before_path, &after_path])
+        .assert()
+        .code(1)
+        .stdout(format!("{}\n", before_path.display()));
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--backup", "--files-with-diff", "-v"])
+        .args([&before_path, &after_path])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", before_path.display()))
+        .stderr(format!(
+            "Formatting {}\nFormatting {}\n",
+            before_path.display(),
+            after_path.display()
+        ));
+
+    assert_eq!(after, read_to_string(&before_path).unwrap());
+    assert_eq!(
+        before,
+        read_to_string(tempdir.path().join("before.bk")).unwrap()
+    );
+    assert!(!tempdir.path().join("after.bk").exists());
+}
+
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn skip_macro_invocations_test() {
+    const ALIGNED: &str = "\
+fn main() {
+    if_chain! {
+        if let Some(x)   = f();
+        if x > 0        ;
+        then { g(x) }
+    }
+}
+";
+
+    let tempdir = tempdir().unwrap();
+
+    let config_path = tempdir.path().join("rustfmt.toml");
+    let path = tempdir.path().join("main.rs");
+
+    write(&path, ALIGNED).unwrap();
+
+    // smoelius: The setting is honored even though `rustfmt` may not honor it, because it is
+    // unstable.
+    for value in ["[\"if_chain\"]", "[\"*\"]"] {
+        write(&config_path, format!("skip_macro_invocations = {value}\n")).unwrap();
+
+        Command::cargo_bin("rustfmt_if_chain")
+            .unwrap()
+            .arg(&path)
+            .assert()
+            .success();
+
+        assert_eq!(ALIGNED, read_to_string(&path).unwrap());
+    }
+
+    write(&config_path, "skip_macro_invocations = [\"other\"]\n").unwrap();
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--check", &path.to_string_lossy()])
+        .assert()
+        .code(1);
+}
+
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn suggest_test() {
+    const ALIASED: &str = "\
+use if_chain::if_chain as chain;
+
+fn main() {
+    if let Some(x) = f() { if let Some(y) = g(x) { h(y); } }
+}
+";
+    const ALIASED_SUGGESTED: &str = "\
+use if_chain::if_chain as chain;
+
+fn main() {
+    chain! {
+        if let Some(x) = f();
+        if let Some(y) = g(x);
+        then {
+            h(y);
+        }
+    }
+}
+";
+
+    const INLINE_MODULE: &str = "\
+fn main() {}
+
+mod m {
+    fn f() {
+        if let Some(x) = f() { if let Some(y) = g(x) { h(y); } }
+    }
+}
+";
+    const INLINE_MODULE_SUGGESTED: &str = "\
+fn main() {}
+
+mod m {
+    use if_chain::if_chain;
+
+    fn f() {
+        if_chain! {
+            if let Some(x) = f();
+            if let Some(y) = g(x);
+            then {
+                h(y);
+            }
+        }
+    }
+}
+";
+
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+
+    let tempdir = tempdir().unwrap();
+
+    let path = tempdir.path().join("suggest.rs");
+
+    copy(examples.join("suggest_before.rs"), &path).unwrap();
+
+    // smoelius: Without `--fix`, the suggestions are printed as a diff and no file is modified.
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .arg("--suggest-if-chain")
+        .arg(&path)
+        .assert()
+        .code(1)
+        .stdout(predicates::str::contains("+    if_chain! {"));
+
+    assert_eq!(
+        read_to_string(examples.join("suggest_before.rs")).unwrap(),
+        read_to_string(&path).unwrap()
+    );
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--suggest-if-chain", "--fix"])
+        .arg(&path)
+        .assert()
+        .success();
+
+    assert_eq!(
+        read_to_string(examples.join("suggest_after.rs")).unwrap(),
+        read_to_string(&path).unwrap()
+    );
+
+    // smoelius: If the macro is imported under another name, that name is used and no `use` item
+    // is added.
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--suggest-if-chain", "--fix"])
+        .write_stdin(ALIASED)
+        .assert()
+        .success()
+        .stdout(ALIASED_SUGGESTED);
+
+    // smoelius: A `use` item does not extend into inline modules, so one is added to each inline
+    // module that gets an invocation.
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--suggest-if-chain", "--fix"])
+        .write_stdin(INLINE_MODULE)
+        .assert()
+        .success()
+        .stdout(INLINE_MODULE_SUGGESTED);
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .arg("--fix")
+        .write_stdin(ALIASED)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "`--fix` requires `--suggest-if-chain`",
+        ));
+}
+
+#[cfg(unix)]
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn width_test() {
+    const UNFORMATTED: &str = "\
+fn main() {
+    if_chain! {
+        if let Some(x) = aaaaaaaaaaaaaaaaaaa;
+        if let Some(x) = aaaaaaaaaaaaaaaaaaaa;
+        if bbbbbbbbbbb && cccccccccccccccccc;
+        if bbbbbbbbbbb && ccccccccccccccccccc;
+        if bbbbbbbbbbb && ccccccccc as u32;
+        if bbbbbbbbbbb && cccccccccccc as u32;
+        if let Some(y) = if_chain! { if a; then { b } else { c } };
+        then { g(x) }
+    }
+    foooooooooooooooooooooo(aa, if_chain! { if a; then { b } });
+    fooooooooooooooooooooooo(aa, if_chain! { if a; then { b } });
+}
+";
+    const FORMATTED: &str = "\
+fn main() {
+    if_chain! {
+        if let Some(x) = aaaaaaaaaaaaaaaaaaa;
+        if let Some(x) =
+            aaaaaaaaaaaaaaaaaaaa;
+        if bbbbbbbbbbb && cccccccccccccccccc;
+        if bbbbbbbbbbb
+            && ccccccccccccccccccc;
+        if bbbbbbbbbbb && ccccccccc as u32;
+        if bbbbbbbbbbb
+            && cccccccccccc as u32;
+        if let Some(y) = if_chain! {
+            if a;
+            then {
+                b
+            } else {
+                c
+            }
+        };
+        then {
+            g(x)
+        }
+    }
+    foooooooooooooooooooooo(aa, if_chain! {
+        if a;
+        then {
+            b
+        }
+    });
+    fooooooooooooooooooooooo(
+        aa,
+        if_chain! {
+            if a;
+            then {
+                b
+            }
+        },
+    );
+}
+";
+
+    let tempdir = tempdir().unwrap();
+
+    let path = tempdir.path().join("main.rs");
+
+    write(tempdir.path().join("rustfmt.toml"), "max_width = 45\n").unwrap();
+    write(&path, UNFORMATTED).unwrap();
+
+    // smoelius: Each line is broken exactly where it would have to be for the final text to fit,
+    // i.e., a clause ending in `;` may be exactly `max_width` wide, and no wider. The exception is
+    // a condition ending in a cast, which is parenthesized, and so may be broken up to two columns
+    // early.
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .arg(&path)
+        .assert()
+        .success();
+
+    assert_eq!(FORMATTED, read_to_string(&path).unwrap());
+}
+
+#[cfg(unix)]
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn replace_test() {
+    use std::{
+        fs::{Permissions, metadata, read_dir, set_permissions},
+        os::unix::fs::PermissionsExt,
+    };
+
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+
+    let tempdir = tempdir().unwrap();
+
+    let before_path = tempdir.path().join("before.rs");
+    let after_path = tempdir.path().join("after.rs");
+
+    copy(examples.join("before.rs"), &before_path).unwrap();
+    copy(examples.join("after.rs"), &after_path).unwrap();
+
+    set_permissions(&before_path, Permissions::from_mode(0o600)).unwrap();
+
+    let modified = metadata(&after_path).unwrap().modified().unwrap();
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args([&before_path, &after_path])
+        .assert()
+        .success();
+
+    assert_eq!(
+        read_to_string(examples.join("after.rs")).unwrap(),
+        read_to_string(&before_path).unwrap()
+    );
+
+    // smoelius: The formatted file keeps its permissions.
+    assert_eq!(
+        0o600,
+        metadata(&before_path).unwrap().permissions().mode() & 0o777
+    );
+
+    // smoelius: The already formatted file is not written.
+    assert_eq!(modified, metadata(&after_path).unwrap().modified().unwrap());
+
+    // smoelius: No temporary files are left behind.
+    assert_eq!(2, read_dir(tempdir.path()).unwrap().count());
+}
+
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn jobs_test() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+
+    let tempdir = tempdir().unwrap();
+
+    let mut paths = Vec::new();
+    for i in 0..8 {
+        let path = tempdir.path().join(format!("{i}.rs"));
+        if i % 3 == 0 {
+            write(&path, "fn f() {\n    let x = ;\n}\n").unwrap();
+        } else {
+            copy(examples.join("before.rs"), &path).unwrap();
+        }
+        paths.push(path);
+    }
+
+    let assert = Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--jobs", "4"])
+        .args(&paths)
+        .env("RUST_BACKTRACE", "0")
+        .assert()
+        .code(1);
+
+    // smoelius: Errors are reported in the order the paths were given.
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    let errors = stderr
+        .lines()
+        .filter_map(|line| line.strip_prefix("Error: failed to format "))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        [0, 3, 6]
+            .map(|i| format!("{:?}:", paths[i]))
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>(),
+        errors
+    );
+
+    for (i, path) in paths.iter().enumerate() {
+        if i % 3 != 0 {
+            assert_eq!(
+                read_to_string(examples.join("after.rs")).unwrap(),
+                read_to_string(path).unwrap()
+            );
+        }
+    }
+}
+
+#[cfg(unix)]
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn batch_test() {
+    use std::{
+        env::{join_paths, split_paths, var_os},
+        fs::{Permissions, remove_file, set_permissions},
+        os::unix::fs::PermissionsExt,
+    };
+
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+
+    let tempdir = tempdir().unwrap();
+
+    // smoelius: Put a `rustfmt` on the `PATH` that logs each invocation before running the real
+    // `rustfmt`.
+    let real_rustfmt = String::from_utf8(
+        std::process::Command::new("which")
+            .arg("rustfmt")
+            .output()
+            .unwrap()
+            .stdout,
+    )
+    .unwrap();
+    let bin = tempdir.path().join("bin");
+    let log = tempdir.path().join("log");
+    create_dir(&bin).unwrap();
+    write(
+        bin.join("rustfmt"),
+        format!(
+            "#!/bin/sh\necho \"$@\" >> {}\nexec {} \"$@\"\n",
+            log.display(),
+            real_rustfmt.trim_end()
+        ),
+    )
+    .unwrap();
+    set_permissions(bin.join("rustfmt"), Permissions::from_mode(0o755)).unwrap();
+    let path = join_paths(
+        [bin]
+            .into_iter()
+            .chain(split_paths(&var_os("PATH").unwrap())),
+    )
+    .unwrap();
+
+    let src = tempdir.path().join("src");
+    create_dir(&src).unwrap();
+    let mut paths = Vec::new();
+    for (before, _) in EXAMPLES {
+        paths.push(src.join(before));
+        copy(examples.join(before), src.join(before)).unwrap();
+    }
+    paths.push(src.join("plain.rs"));
+    write(src.join("plain.rs"), "fn  main() {}\n").unwrap();
+
+    // smoelius: Checking the files uses as many `rustfmt` invocations as formatting them does (see
+    // below).
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--check", "--jobs", "1"])
+        .args(&paths)
+        .env("PATH", &path)
+        .assert()
+        .code(1);
+
+    assert_eq!(2, read_to_string(&log).unwrap().lines().count());
+    remove_file(&log).unwrap();
+
+    Command::cargo_bin("rustfmt_if_chain")
+        .unwrap()
+        .args(["--jobs", "1"])
+        .args(&paths)
+        .env("PATH", path)
+        .assert()
+        .success();
+
+    for (before, after) in EXAMPLES {
+        assert_eq!(
+            read_to_string(examples.join(after)).unwrap(),
+            read_to_string(src.join(before)).unwrap()
+        );
+    }
+    assert_eq!(
+        "fn main() {}\n",
+        read_to_string(src.join("plain.rs")).unwrap()
+    );
+
+    // smoelius: All of the files are formatted by a single `rustfmt` invocation. The files that use
+    // `if_chain!` and that changed are formatted again by a second invocation, which confirms that
+    // they reached a fixpoint. `plain.rs` is not among them.
+    let log = read_to_string(log).unwrap();
+    assert_eq!(2, log.lines().count());
+    assert_eq!(
+        EXAMPLES.len(),
+        log.lines()
+            .nth(1)
+            .unwrap()
+            .split(' ')
+            .filter(|arg| Path::new(arg).extension().is_some_and(|ext| ext == "rs"))
+            .count()
+    );
+}
+
+#[test]
+fn library_test() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+
+    for (before, after) in EXAMPLES {
+        let tempdir = tempdir().unwrap();
+
+        let mut options = Options::default();
+        options.path = Some(tempdir.path().join(before));
+
+        let formatted =
+            format_str(&read_to_string(examples.join(before)).unwrap(), &options).unwrap();
+
+        assert_eq!(read_to_string(examples.join(after)).unwrap(), formatted);
+    }
+}
+
+#[cfg_attr(
+    dylint_lib = "non_thread_safe_call_in_test",
+    allow(non_thread_safe_call_in_test)
+)]
+#[test]
+fn library_warnings_test() {
+    let source = "fn main() {\n    let y = if_chain!(if a; thn { 1 });\n}\n";
+
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+
+    let mut options = Options::default();
+    options.path = Some(PathBuf::from("malformed.rs"));
+    options.on_warning = WarningHandler::new({
+        let warnings = warnings.clone();
+        move |warning| warnings.lock().unwrap().push(warning.to_owned())
+    });
+
+    assert_eq!(source, format_str(source, &options).unwrap());
+
+    assert_eq!(
+        vec![String::from(
+            "malformed.rs:2:29: malformed `if_chain!` invocation was left unformatted: expected \
+             `if`, `let`, or `then`"
+        )],
+        *warnings.lock().unwrap()
+    );
+}
+
 #[cfg(nightly)]
 #[cfg_attr(
     dylint_lib = "non_thread_safe_call_in_test",
@@ -1,7 +1,6 @@
 use assert_cmd::Command;
-use std::{env::remove_var, ffi::OsStr, fs::OpenOptions, io::Write};
+use std::{env::remove_var, fs::OpenOptions, io::Write};
 use tempfile::tempdir_in;
-use walkdir::WalkDir;
 
 #[ctor::ctor]
 fn initialize() {
@@ -60,29 +59,17 @@ const EXCEPTIONS: &[&str] = &[
 fn format_clippy() {
     let tempdir = tempdir_in(".").unwrap();
 
-    let crashes = tempdir.path().join("tests/ui/crashes");
-
     Command::new("git")
         .args(["clone", CLIPPY_URL, &tempdir.path().to_string_lossy()])
         .assert()
         .success();
 
-    for entry in WalkDir::new(&tempdir) {
-        let entry = entry.unwrap();
-        let path = entry.path();
-        if path.extension() != Some(OsStr::new("rs"))
-            || path.starts_with(&crashes)
-            || EXCEPTIONS.iter().any(|child| path.ends_with(child))
-        {
-            continue;
-        }
-        Command::cargo_bin("rustfmt_if_chain")
-            .unwrap()
-            .current_dir(&tempdir)
-            .arg(path)
-            .assert()
-            .success();
+    let mut command = Command::cargo_bin("rustfmt_if_chain").unwrap();
+    command.current_dir(&tempdir);
+    for exception in ["tests/ui/crashes"].iter().chain(EXCEPTIONS) {
+        command.args(["--exclude", exception]);
     }
+    command.arg(".").assert().success();
 
     let mut file = OpenOptions::new()
         .append(true)